@@ -1,17 +1,22 @@
 mod subscriptions;
 mod helpers;
 
-use std::time::Duration;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 use iced::widget::{column, row, button, container, image, stack, text};
-use iced::{Alignment, Element, Fill, Font, Subscription, Theme};
-use crate::app::helpers::metric_row;
-use crate::cv::TimeMetrics;
+use iced::{Alignment, Element, Fill, Font, Subscription, Task, Theme};
+use crate::app::helpers::{background, benchmark_panel, confidence_panel, metric_row, settings_panel, state_label, tensor_panel};
+use crate::app::subscriptions::Service;
+use crate::camera::{CameraEvent, CaptureSummary};
+use crate::config::Config;
+use crate::cv::{BatchMetrics, BenchmarkReport, COCO_KEYPOINT_NAMES, ConfidenceStats, RecordingSummary, TaskSettings, JitterStats, ModelInfo, TensorSummary, TimeMetrics, TopDownMetrics};
 use crate::{Frame, Inference};
-use crate::utils::ManagedService;
+use crate::utils::{ManagedService, ServiceState};
 
 enum InferenceState {
     Unloaded,
+    Loading { id: u64, started: Instant },
     Stopped,
     Running,
 }
@@ -33,64 +38,397 @@ pub struct App {
     cam_frame: Option<image::Handle>,
     cv_frame: Option<image::Handle>,
     
-    model_load_time: Option<Duration>,
+    model_info: Option<ModelInfo>,
     time_metrics: Option<TimeMetrics>,
+    jitter: Option<JitterStats>,
+    top_down: Option<TopDownMetrics>,
+    tensors: Option<Vec<TensorSummary>>,
+    batch: Option<BatchMetrics>,
+    confidence: Option<ConfidenceStats>,
 
     inference_state: InferenceState,
+    /// Tags load requests so results of cancelled loads are ignored
+    next_load_id: u64,
+    error: Option<String>,
+
+    camera_state: ServiceState,
+    cv_state: ServiceState,
+    /// Set while the camera is lost or being reopened
+    camera_banner: Option<String>,
+    /// Camera frames are ignored and the frozen frame is shown instead
+    paused: bool,
+    /// Last file written: a fixture, snapshot, recording, capture or settings
+    saved: Option<String>,
+    /// Kept for snapshots
+    last_inference: Option<Inference>,
+    recording: bool,
+    capturing: bool,
+    benchmarking: bool,
+    benchmark: Option<BenchmarkReport>,
+    /// Live thresholds and filters, pushed to the worker on every change
+    settings: TaskSettings,
+    /// Text of the hidden classes field, parsed into `settings`
+    hidden_classes: String,
 }
 
+/// Frozen frames are saved here for regression tests
+const FIXTURE_DIR: &str = "fixtures";
+
 #[derive(Debug, Clone)]
 pub enum Message {
     CamFrame(image::Handle),
-    CvInference((image::Handle, TimeMetrics)),
+    CvInference(Box<(image::Handle, Inference)>),
     LoadModelPressed,
+    ModelLoaded(u64, Result<ModelInfo, String>),
+    CancelLoadPressed,
+    LoadingTick,
+    ServiceStatus((Service, ServiceState)),
+    Camera(CameraEvent),
     StartInferencePressed,
     StopInferencePressed,
+    PausePressed,
+    ResumePressed,
+    StepPressed,
+    SaveFramePressed,
+    FrameSaved(Result<PathBuf, String>),
+    SnapshotPressed,
+    RecordPressed,
+    StopRecordPressed,
+    RecordingStopped(Result<RecordingSummary, String>),
+    CapturePressed,
+    StopCapturePressed,
+    CaptureStopped(Result<CaptureSummary, String>),
+    BenchmarkPressed,
+    BenchmarkFinished(Result<BenchmarkReport, String>),
+    ConfidenceChanged(f32),
+    IouChanged(f32),
+    KeypointToggled(usize, bool),
+    HiddenClassesChanged(String),
+    SaveSettingsPressed,
+    SettingsSaved(Result<(), String>),
 }
 
 impl App {
     fn new(pipelines: crate::Pipelines) -> Self {
+        let settings = pipelines.cv_manager.settings();
+        let hidden_classes = settings.hidden_classes
+            .iter()
+            .map(usize::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+
         Self {
             pipelines,
             cam_frame: None,
             cv_frame: None,
-            model_load_time: None,
+            model_info: None,
             time_metrics: None,
+            jitter: None,
+            top_down: None,
+            tensors: None,
+            batch: None,
+            confidence: None,
             inference_state: InferenceState::Unloaded,
+            next_load_id: 0,
+            error: None,
+            camera_state: ServiceState::Stopped,
+            cv_state: ServiceState::Stopped,
+            camera_banner: None,
+            paused: false,
+            saved: None,
+            last_inference: None,
+            recording: false,
+            capturing: false,
+            benchmarking: false,
+            benchmark: None,
+            settings,
+            hidden_classes,
+        }
+    }
+
+    /// State to return to when a load fails or is cancelled; a previously
+    /// loaded model is still in place
+    fn idle_state(&self) -> InferenceState {
+        match self.model_info {
+            Some(_) => InferenceState::Stopped,
+            None => InferenceState::Unloaded,
         }
     }
 
-    fn update(&mut self, message: Message) {
+    fn update(&mut self, message: Message) -> Task<Message> {
+        let mut task = Task::none();
         match message {
             Message::CamFrame(frame) => {
-                self.cam_frame = Some(frame);
+                if !self.paused {
+                    self.cam_frame = Some(frame);
+                }
             }
-            Message::CvInference((frame, inf_time)) => {
+            Message::CvInference(inference) => {
+                let (frame, inference) = *inference;
+                self.last_inference = Some(inference.clone());
+                if self.paused {
+                    let (width, height, rgba) = &inference.source;
+                    self.cam_frame = Some(image::Handle::from_rgba(*width, *height, rgba.data.clone()));
+                }
                 self.cv_frame = Some(frame);
-                self.time_metrics = Some(inf_time);
+                if self.recording && self.pipelines.recorder.has_ended() {
+                    task = self.stop_recording();
+                }
+                self.time_metrics = Some(inference.time_metrics);
+                self.jitter = inference.jitter;
+                self.top_down = inference.top_down;
+                self.tensors = inference.tensors;
+                self.batch = inference.batch;
+                self.confidence = inference.confidence;
             }
             Message::LoadModelPressed => {
-                match self.pipelines.cv_manager.load_model() {
-                    Ok(elapsed) => {
-                        self.model_load_time = Some(elapsed);
+                let id = self.next_load_id;
+                self.next_load_id += 1;
+                self.error = None;
+                self.inference_state = InferenceState::Loading { id, started: Instant::now() };
+
+                let cv_manager = self.pipelines.cv_manager.clone();
+                return background(move || cv_manager.load_model(), move |result| Message::ModelLoaded(id, result));
+            }
+            Message::ModelLoaded(id, result) => {
+                if !matches!(self.inference_state, InferenceState::Loading { id: current, .. } if current == id) {
+                    return Task::none();
+                }
+                match result {
+                    Ok(info) => {
+                        self.model_info = Some(info);
                     }
                     Err(e) => {
-                        eprintln!("Unable to load model: {}", e)
+                        self.error = Some(format!("Unable to load model: {e}"));
                     }
-                };
-                self.inference_state = InferenceState::Stopped;
+                }
+                self.inference_state = self.idle_state();
+            }
+            Message::CancelLoadPressed => {
+                self.pipelines.cv_manager.cancel_load();
+                self.inference_state = self.idle_state();
+            }
+            Message::LoadingTick => {}
+            Message::ServiceStatus((service, state)) => {
+                if let ServiceState::Failed(e) = &state
+                    && let InferenceState::Running = self.inference_state
+                {
+                    self.error = Some(format!("{service:?} failed: {e}"));
+                    task = self.stop_services();
+                }
+
+                match service {
+                    Service::Camera => {
+                        if matches!(state, ServiceState::Stopped | ServiceState::Failed(_)) {
+                            self.camera_banner = None;
+                        }
+                        // Only a replay stops on its own; the last frame would stay frozen
+                        if state == ServiceState::Stopped
+                            && let InferenceState::Running = self.inference_state
+                            && self.pipelines.camera_manager.is_replay()
+                        {
+                            self.camera_banner = Some("Replay finished".to_string());
+                            task = self.stop_services();
+                        }
+                        self.camera_state = state;
+                    }
+                    Service::Cv => self.cv_state = state,
+                }
             }
+            Message::Camera(event) => match event {
+                CameraEvent::Connected { .. } => self.camera_banner = None,
+                CameraEvent::CaptureError(_) => {}
+                CameraEvent::Disconnected(e) => {
+                    self.camera_banner = Some(format!("Camera disconnected: {e}"));
+                }
+                CameraEvent::Reconnecting { attempt, delay } => {
+                    self.camera_banner = Some(format!("Camera disconnected, reconnecting (attempt {attempt}, waiting {delay:?})"));
+                }
+            },
             Message::StartInferencePressed => {
-                self.pipelines.camera_manager.start().expect("Unable to start camera");
-                self.pipelines.cv_manager.start().expect("Unable to start model");
+                self.error = None;
+
+                if let Err(e) = self.pipelines.camera_manager.start() {
+                    self.error = Some(format!("Unable to start camera: {e}"));
+                    return Task::none();
+                }
+                if let Err(e) = self.pipelines.cv_manager.start() {
+                    self.error = Some(format!("Unable to start model: {e}"));
+                    let _ = self.pipelines.camera_manager.stop();
+                    return Task::none();
+                }
                 self.inference_state = InferenceState::Running;
             }
             Message::StopInferencePressed => {
-                self.pipelines.camera_manager.stop();
-                self.pipelines.cv_manager.stop();
-                self.inference_state = InferenceState::Stopped;
+                return self.stop_services();
+            }
+            Message::PausePressed => {
+                self.pipelines.cv_manager.pause();
+                self.paused = true;
+            }
+            Message::ResumePressed => {
+                self.pipelines.cv_manager.resume();
+                self.paused = false;
+            }
+            Message::StepPressed => {
+                self.pipelines.cv_manager.step();
+            }
+            Message::SaveFramePressed => {
+                let cv_manager = self.pipelines.cv_manager.clone();
+                return background(move || cv_manager.save_frozen(Path::new(FIXTURE_DIR)), Message::FrameSaved);
             }
+            Message::FrameSaved(result) => match result {
+                Ok(path) => self.saved = Some(path.display().to_string()),
+                Err(e) => self.error = Some(format!("Unable to save frame: {e}")),
+            },
+            Message::SnapshotPressed => {
+                let Some(inference) = self.last_inference.clone() else {
+                    return Task::none();
+                };
+                let recorder = self.pipelines.recorder.clone();
+                return background(move || recorder.snapshot(&inference), Message::FrameSaved);
+            }
+            Message::RecordPressed => {
+                match self.pipelines.recorder.start() {
+                    Ok(path) => {
+                        self.recording = true;
+                        self.saved = Some(format!("Recording to {}", path.display()));
+                    }
+                    Err(e) => self.error = Some(format!("Unable to record: {e}")),
+                }
+            }
+            Message::StopRecordPressed => {
+                return self.stop_recording();
+            }
+            Message::RecordingStopped(result) => {
+                self.recording = false;
+                match result {
+                    Ok(summary) => {
+                        let ended = summary.ended.map(|reason| format!(", stopped: {reason}")).unwrap_or_default();
+                        self.saved = Some(format!(
+                            "{} ({} frames, {} dropped{ended})",
+                            summary.path.display(),
+                            summary.frames,
+                            summary.dropped
+                        ));
+                    }
+                    Err(e) => self.error = Some(format!("Recording failed: {e}")),
+                }
+            }
+            Message::CapturePressed => match self.pipelines.camera_manager.start_capture() {
+                Ok(path) => {
+                    self.capturing = true;
+                    self.saved = Some(format!("Capturing to {}", path.display()));
+                }
+                Err(e) => self.error = Some(format!("Unable to capture: {e}")),
+            },
+            Message::StopCapturePressed => {
+                let camera_manager = self.pipelines.camera_manager.clone();
+                return background(move || camera_manager.stop_capture(), Message::CaptureStopped);
+            }
+            Message::CaptureStopped(result) => {
+                self.capturing = false;
+                match result {
+                    Ok(summary) => {
+                        self.saved = Some(format!(
+                            "{} ({} frames, {} dropped, {:.1} MB)",
+                            summary.path.display(),
+                            summary.frames,
+                            summary.dropped,
+                            summary.bytes as f64 / 1_000_000.0
+                        ));
+                    }
+                    Err(e) => self.error = Some(format!("Capture failed: {e}")),
+                }
+            }
+            Message::BenchmarkPressed => {
+                let rx = match self.pipelines.cv_manager.request_benchmark() {
+                    Ok(rx) => rx,
+                    Err(e) => {
+                        self.error = Some(format!("Unable to benchmark: {e}"));
+                        return Task::none();
+                    }
+                };
+                self.error = None;
+                self.benchmarking = true;
+                return Task::perform(
+                    async move { rx.await.unwrap_or_else(|_| Err("Model worker stopped".to_string())) },
+                    Message::BenchmarkFinished,
+                );
+            }
+            Message::BenchmarkFinished(result) => {
+                self.benchmarking = false;
+                match result {
+                    Ok(report) => self.benchmark = Some(report),
+                    Err(e) => self.error = Some(format!("Benchmark failed: {e}")),
+                }
+            }
+            Message::ConfidenceChanged(value) => {
+                self.settings.confidence_threshold = value;
+                self.push_settings();
+            }
+            Message::IouChanged(value) => {
+                self.settings.iou_threshold = value;
+                self.push_settings();
+            }
+            Message::KeypointToggled(k, shown) => {
+                self.settings.hidden_keypoints.retain(|&h| h != k);
+                if !shown {
+                    self.settings.hidden_keypoints.push(k);
+                    self.settings.hidden_keypoints.sort_unstable();
+                }
+                self.push_settings();
+            }
+            Message::HiddenClassesChanged(input) => {
+                // Ignore what doesn't parse yet, e.g. a trailing comma
+                self.settings.hidden_classes = input
+                    .split(',')
+                    .filter_map(|id| id.trim().parse().ok())
+                    .collect();
+                self.hidden_classes = input;
+                self.push_settings();
+            }
+            Message::SaveSettingsPressed => {
+                let settings = self.settings.clone();
+                return background(
+                    move || Config::save_task_settings(crate::CONFIG_PATH, &settings),
+                    Message::SettingsSaved,
+                );
+            }
+            Message::SettingsSaved(result) => match result {
+                Ok(()) => self.saved = Some(crate::CONFIG_PATH.to_string()),
+                Err(e) => self.error = Some(format!("Unable to save settings: {e}")),
+            },
         }
+        task
+    }
+
+    fn push_settings(&self) {
+        self.pipelines.cv_manager.update_settings(self.settings.clone());
+    }
+
+    /// Also finishes a recording, which would otherwise stay open
+    fn stop_services(&mut self) -> Task<Message> {
+        let results = [
+            self.pipelines.camera_manager.stop(),
+            self.pipelines.cv_manager.stop(),
+        ];
+        if let Some(Err(e)) = results.into_iter().find(Result::is_err) {
+            self.error = Some(format!("Unable to stop cleanly: {e}"));
+        }
+        self.pipelines.cv_manager.resume();
+        self.paused = false;
+        self.inference_state = InferenceState::Stopped;
+
+        if self.recording { self.stop_recording() } else { Task::none() }
+    }
+
+    /// Finish the recording off the UI thread; ffmpeg may still be encoding
+    fn stop_recording(&mut self) -> Task<Message> {
+        // Not again for inferences arriving before `RecordingStopped`
+        self.recording = false;
+        let recorder = self.pipelines.recorder.clone();
+        background(move || recorder.stop(), Message::RecordingStopped)
     }
 
     fn view(&self) -> Element<'_, Message> {
@@ -115,6 +453,10 @@ impl App {
             InferenceState::Running => {
                 button("Load Model")
             }
+            InferenceState::Loading { .. } => {
+                button("Cancel Loading")
+                    .on_press(Message::CancelLoadPressed)
+            }
             InferenceState::Stopped | InferenceState::Unloaded => {
                 button("Load Model")
                     .on_press(Message::LoadModelPressed)
@@ -130,11 +472,43 @@ impl App {
                 button("Start Model")
                     .on_press(Message::StartInferencePressed)
             }
-            InferenceState::Unloaded => {
+            InferenceState::Unloaded | InferenceState::Loading { .. } => {
                 button("Start Model")
             }
         };
 
+        let running = matches!(self.inference_state, InferenceState::Running);
+
+        let pause_button = if self.paused {
+            button("Resume").on_press_maybe(running.then_some(Message::ResumePressed))
+        } else {
+            button("Pause").on_press_maybe(running.then_some(Message::PausePressed))
+        };
+
+        let step_button = button("Step Frame")
+            .on_press_maybe((running && self.paused).then_some(Message::StepPressed));
+
+        let save_button = button("Save Frame")
+            .on_press_maybe((running && self.paused).then_some(Message::SaveFramePressed));
+
+        let benchmark_button = button(if self.benchmarking { "Benchmarking..." } else { "Benchmark" })
+            .on_press_maybe((running && !self.benchmarking).then_some(Message::BenchmarkPressed));
+
+        let snapshot_button = button("Snapshot")
+            .on_press_maybe(self.last_inference.is_some().then_some(Message::SnapshotPressed));
+
+        let record_button = if self.recording {
+            button("Stop Recording").on_press(Message::StopRecordPressed)
+        } else {
+            button("Record").on_press_maybe(running.then_some(Message::RecordPressed))
+        };
+
+        let capture_button = if self.capturing {
+            button("Stop Capture").on_press(Message::StopCapturePressed)
+        } else {
+            button("Capture Raw").on_press_maybe(running.then_some(Message::CapturePressed))
+        };
+
         let model_load_label = row![
             text("Model Load Time: ")
             .font(Font {
@@ -142,9 +516,13 @@ impl App {
                 ..Font::DEFAULT
             }).size(16),
             text(
-                self.model_load_time
-                    .map(|t| format!("{:?}", t))
-                    .unwrap_or_else(|| "Not loaded".to_string())
+                match (&self.inference_state, &self.model_info) {
+                    (InferenceState::Loading { started, .. }, _) => {
+                        format!("Loading... {:.1}s", started.elapsed().as_secs_f32())
+                    }
+                    (_, Some(info)) => format!("{:?}", info.load_time),
+                    (_, None) => "Not loaded".to_string(),
+                }
             )
             .size(16)
         ].spacing(5);
@@ -169,19 +547,76 @@ impl App {
             self.time_metrics.map(|t| format!("{:?}", t.render)),
         );
 
-        let content = column![
+        let jitter_label = metric_row(
+            "Jitter (raw / smoothed):",
+            self.jitter.map(|j| format!("{:.2}px / {:.2}px", j.raw, j.smoothed)),
+        );
+
+        let warmup_label = metric_row(
+            "First / Warm Inference:",
+            self.model_info.as_ref().and_then(|info| info.warmup).map(|w| match w.steady {
+                Some(steady) => format!("{:?} / {:?}", w.first, steady),
+                None => format!("{:?} / -", w.first),
+            }),
+        );
+
+        let decoding_label = metric_row(
+            "Decoding:",
+            self.model_info.as_ref().and_then(|info| info.decoding.clone()),
+        );
+
+        let detector_label = metric_row(
+            "Detector Time:",
+            self.top_down.as_ref().map(|t| format!("{:?}", t.detector.preprocess + t.detector.inference + t.detector.postprocess)),
+        );
+
+        let per_person_label = metric_row(
+            "Pose Time per Person:",
+            self.top_down.as_ref().map(|t| {
+                let total: Duration = t.people.iter().map(|p| p.preprocess + p.inference + p.postprocess).sum();
+                let avg = total.checked_div(t.people.len() as u32).unwrap_or_default();
+                format!("{:?} x {}", avg, t.people.len())
+            }),
+        );
+
+        let camera_state_label = metric_row("Camera:", Some(state_label(&self.camera_state)));
+        let cv_state_label = metric_row("Model Worker:", Some(state_label(&self.cv_state)));
+
+        let batch_label = metric_row(
+            "Batch Inference:",
+            self.batch.map(|b| format!("{:?} x {} ({:?} / frame)", b.inference, b.size, b.per_frame)),
+        );
+
+        let mut content = column![
             img,
             row![
                 load_button,
-                control_button
-            ].spacing(40),
+                control_button,
+                pause_button,
+                step_button,
+                save_button,
+                benchmark_button,
+                snapshot_button,
+                record_button,
+                capture_button,
+            ].spacing(20),
             row![
-                model_load_label,
                 column![
+                    model_load_label,
+                    warmup_label,
+                    decoding_label,
+                    detector_label,
+                    per_person_label,
+                    batch_label,
+                ],
+                column![
+                    camera_state_label,
+                    cv_state_label,
                     preprocess_time_label,
                     inference_time_label,
                     postprocess_time_label,
                     render_time_label,
+                    jitter_label,
                 ]
             ].spacing(40)
         ]
@@ -189,6 +624,55 @@ impl App {
         .padding(20)
         .align_x(Alignment::Center);
 
+        if let Some(banner) = &self.camera_banner {
+            content = content.push(
+                container(text(banner.clone()))
+                    .padding(10)
+                    .width(Fill)
+                    .style(container::warning),
+            );
+        }
+
+        content = content.push(settings_panel(
+            &self.settings,
+            &self.hidden_classes,
+            self.pipelines.cv_manager.inference_type(),
+        ));
+
+        if let Some(stats) = &self.confidence {
+            let low: Vec<&str> = stats.keypoints
+                .iter()
+                .filter(|k| k.warning)
+                .map(|k| COCO_KEYPOINT_NAMES[k.index])
+                .collect();
+            if !low.is_empty() {
+                content = content.push(
+                    text(format!("Mostly below the confidence threshold: {}", low.join(", ")))
+                        .style(text::warning),
+                );
+            }
+        }
+
+        if let Some(error) = &self.error {
+            content = content.push(text(error.clone()).style(text::danger));
+        }
+
+        if let Some(saved) = &self.saved {
+            content = content.push(metric_row("Saved:", Some(saved.clone())));
+        }
+
+        if let Some(report) = &self.benchmark {
+            content = content.push(benchmark_panel(report));
+        }
+
+        if let Some(stats) = &self.confidence {
+            content = content.push(confidence_panel(stats));
+        }
+
+        if let Some(tensors) = &self.tensors {
+            content = content.push(tensor_panel(tensors));
+        }
+
         container(content)
             .width(Fill)
             .height(Fill)
@@ -198,10 +682,20 @@ impl App {
     }
 
     fn subscription(&self) -> Subscription<Message> {
-        Subscription::batch(vec![
+        let mut subscriptions = vec![
             subscriptions::raw_frame_subscription(self.pipelines.camera_manager.clone()).map(Message::CamFrame),
-            subscriptions::inference_subscription(self.pipelines.cv_manager.clone()).map(Message::CvInference),
-        ])
+            subscriptions::inference_subscription(self.pipelines.cv_manager.clone()).map(|i| Message::CvInference(Box::new(i))),
+            subscriptions::camera_event_subscription(self.pipelines.camera_manager.clone()).map(Message::Camera),
+            subscriptions::status_subscription(Service::Camera, self.pipelines.camera_manager.status()).map(Message::ServiceStatus),
+            subscriptions::status_subscription(Service::Cv, self.pipelines.cv_manager.status()).map(Message::ServiceStatus),
+        ];
+
+        // Redraw the elapsed load time
+        if let InferenceState::Loading { .. } = self.inference_state {
+            subscriptions.push(iced::time::every(Duration::from_millis(100)).map(|_| Message::LoadingTick));
+        }
+
+        Subscription::batch(subscriptions)
     }
 
     fn theme(&self) -> Theme {
@@ -1,6 +1,30 @@
-use iced::{Font, widget::{row, text}};
+use std::fmt::Display;
 
-use crate::app::Message;
+use iced::{Font, Task, widget::{Column, button, checkbox, column, row, slider, text, text_input}};
+
+use crate::{app::Message, cv::{BenchmarkReport, COCO_KEYPOINT_NAMES, ConfidenceStats, InfType, TaskSettings, TensorSummary, TimingStats}, utils::ServiceState};
+
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Run blocking `work` off the UI thread and hand its result to `done`.
+/// Errors, including a panic in `work`, arrive as strings.
+pub fn background<T, E>(
+    work: impl FnOnce() -> Result<T, E> + Send + 'static,
+    done: impl FnOnce(Result<T, String>) -> Message + Send + 'static,
+) -> Task<Message>
+where
+    T: Send + 'static,
+    E: Display,
+{
+    Task::perform(
+        async move {
+            tokio::task::spawn_blocking(move || work().map_err(|e| e.to_string()))
+                .await
+                .unwrap_or_else(|e| Err(e.to_string()))
+        },
+        done,
+    )
+}
 
 pub fn metric_row(label: impl Into<String>, value: Option<String>) -> iced::widget::Row<'static, Message> {
     let label = label.into();
@@ -16,4 +40,124 @@ pub fn metric_row(label: impl Into<String>, value: Option<String>) -> iced::widg
             .size(16)
     ]
     .spacing(5)
-}
\ No newline at end of file
+}
+
+/// One line per model output: name, dtype, shape, min/max/mean and a
+/// histogram sparkline
+pub fn tensor_panel(tensors: &[TensorSummary]) -> Column<'static, Message> {
+    let rows = tensors.iter().map(|t| {
+        let stats = t.stats.as_ref().map(|s| {
+            let peak = s.histogram.iter().copied().max().unwrap_or(0).max(1);
+            let sparkline: String = s.histogram
+                .iter()
+                .map(|&n| BARS[(n as usize * (BARS.len() - 1)).div_ceil(peak as usize)])
+                .collect();
+            format!("min {:.3}  max {:.3}  mean {:.3}  {}", s.min, s.max, s.mean, sparkline)
+        })
+        .unwrap_or_else(|| "non-numeric".to_string());
+
+        metric_row(format!("{} {} {:?}:", t.name, t.dtype, t.shape), Some(stats)).into()
+    });
+
+    column(rows).spacing(4)
+}
+
+/// Inference and end-to-end timing distributions of a fixed-input benchmark
+pub fn benchmark_panel(report: &BenchmarkReport) -> Column<'static, Message> {
+    let stats = |s: &TimingStats| {
+        format!(
+            "mean {:?} ± {:?}  min {:?}  p50 {:?}  p95 {:?}  max {:?}  CV {:.1}%",
+            s.mean, s.std_dev, s.min, s.p50, s.p95, s.max, s.cv * 100.0
+        )
+    };
+
+    column![
+        metric_row("Benchmark:", Some(format!("{} x{}", report.source, report.iterations))),
+        metric_row("Inference:", Some(stats(&report.inference))),
+        metric_row("Total:", Some(stats(&report.total))),
+    ]
+    .spacing(4)
+}
+
+/// Threshold sliders and overlay toggles for the task in use
+pub fn settings_panel(
+    settings: &TaskSettings,
+    hidden_classes: &str,
+    inference_type: InfType,
+) -> Column<'static, Message> {
+    let threshold = |label: &str, value: f32, on_change: fn(f32) -> Message| {
+        row![
+            metric_row(label.to_string(), Some(format!("{value:.2}"))).width(260),
+            slider(0.0..=1.0, value, on_change).step(0.01).width(300),
+        ]
+        .spacing(10)
+    };
+
+    let mut panel = column![
+        threshold("Confidence:", settings.confidence_threshold, Message::ConfidenceChanged),
+        threshold("NMS IoU:", settings.iou_threshold, Message::IouChanged),
+    ]
+    .spacing(8);
+
+    match inference_type {
+        InfType::Pose => {
+            let toggles = COCO_KEYPOINT_NAMES.iter().enumerate().map(|(k, name)| {
+                checkbox(!settings.hidden_keypoints.contains(&k))
+                    .label(*name)
+                    .on_toggle(move |shown| Message::KeypointToggled(k, shown))
+                    .into()
+            });
+            panel = panel.push(iced::widget::Row::with_children(toggles).spacing(10).wrap());
+        }
+        InfType::BoundingBox => {
+            panel = panel.push(
+                row![
+                    text("Hidden classes:").size(16),
+                    text_input("e.g. 0, 2, 7", hidden_classes)
+                        .on_input(Message::HiddenClassesChanged)
+                        .width(300),
+                ]
+                .spacing(10),
+            );
+        }
+        _ => {}
+    }
+
+    panel.push(button("Save Settings").on_press(Message::SaveSettingsPressed))
+}
+
+/// Trimmed keypoint counts and one line of recent confidence per keypoint
+pub fn confidence_panel(stats: &ConfidenceStats) -> Column<'static, Message> {
+    let rows = stats.keypoints.iter().map(|k| {
+        metric_row(
+            format!("{}:", COCO_KEYPOINT_NAMES[k.index]),
+            Some(format!(
+                "mean {:.2}  below {:.0}%  trimmed {}",
+                k.mean,
+                k.low_ratio * 100.0,
+                k.trimmed_total
+            )),
+        )
+        .into()
+    });
+
+    column![
+        metric_row(
+            "Discarded Detections:",
+            Some(format!("{} this frame / {} total", stats.discarded, stats.discarded_total)),
+        ),
+        metric_row(
+            "Trimmed Keypoints:",
+            Some(format!("{} this frame / {} total", stats.trimmed, stats.trimmed_total)),
+        ),
+    ]
+    .extend(rows)
+    .spacing(4)
+}
+
+pub fn state_label(state: &ServiceState) -> String {
+    match state {
+        ServiceState::Failed(e) => format!("Failed: {e}"),
+        state => format!("{state:?}"),
+    }
+}
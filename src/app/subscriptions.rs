@@ -8,9 +8,8 @@ use iced::Subscription;
 
 use tokio::sync::broadcast;
 
-use crate::cv::TimeMetrics;
-use crate::{camera::CameraManager, cv::CVManager};
-use crate::utils::ManagedService;
+use crate::{camera::{CameraEvent, CameraManager}, cv::CVManager};
+use crate::utils::{ManagedService, ServiceState};
 
 use super::{Frame, Inference};
 
@@ -60,7 +59,7 @@ impl iced_subscription::Recipe for CameraSubscription {
    CV Subscription
    ============================ */
 
-pub fn inference_subscription(cv_manager: Arc<CVManager>) -> Subscription<(image::Handle, TimeMetrics)> {
+pub fn inference_subscription(cv_manager: Arc<CVManager>) -> Subscription<(image::Handle, Inference)> {
     let rx = cv_manager.subscribe();
     iced_subscription::from_recipe(CVSubscription::new(rx))
 }
@@ -76,8 +75,7 @@ impl CVSubscription {
 }
 
 impl iced_subscription::Recipe for CVSubscription {
-    type Output = (image::Handle, TimeMetrics);
-    //TODO: make the output inferred from Inference type but still transform frame to handle
+    type Output = (image::Handle, Inference);
 
     fn hash(&self, state: &mut Hasher) {
         use std::hash::Hash;
@@ -92,10 +90,100 @@ impl iced_subscription::Recipe for CVSubscription {
 
         let s = async_stream::stream! {
             while let Ok(inference) = rx.recv().await {
-                let frame = inference.frame;
-                yield (image::Handle::from_rgba(frame.0, frame.1, frame.2.data.clone()), inference.time_metrics);
+                let frame = &inference.frame;
+                let handle = image::Handle::from_rgba(frame.0, frame.1, frame.2.data.clone());
+                yield (handle, inference);
             }
         };
         Box::pin(s)
     }
-}
\ No newline at end of file
+}
+
+/* ============================
+   Camera Event Subscription
+   ============================ */
+
+pub fn camera_event_subscription(camera_manager: Arc<CameraManager>) -> Subscription<CameraEvent> {
+    let rx = camera_manager.events();
+    iced_subscription::from_recipe(CameraEventSubscription { rx })
+}
+
+struct CameraEventSubscription {
+    rx: broadcast::Receiver<CameraEvent>,
+}
+
+impl iced_subscription::Recipe for CameraEventSubscription {
+    type Output = CameraEvent;
+
+    fn hash(&self, state: &mut Hasher) {
+        use std::hash::Hash;
+        std::any::TypeId::of::<Self>().hash(state);
+    }
+
+    fn stream(
+        self: Box<Self>,
+        _input: stream::BoxStream<iced_subscription::Event>,
+    ) -> stream::BoxStream<Self::Output> {
+        let mut rx = self.rx;
+
+        let s = async_stream::stream! {
+            loop {
+                match rx.recv().await {
+                    Ok(event) => yield event,
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        };
+        Box::pin(s)
+    }
+}
+
+/* ============================
+   Service Status Subscription
+   ============================ */
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Service {
+    Camera,
+    Cv,
+}
+
+pub fn status_subscription(service: Service, rx: broadcast::Receiver<ServiceState>) -> Subscription<(Service, ServiceState)> {
+    iced_subscription::from_recipe(StatusSubscription { service, rx })
+}
+
+struct StatusSubscription {
+    service: Service,
+    rx: broadcast::Receiver<ServiceState>,
+}
+
+impl iced_subscription::Recipe for StatusSubscription {
+    type Output = (Service, ServiceState);
+
+    fn hash(&self, state: &mut Hasher) {
+        use std::hash::Hash;
+        std::any::TypeId::of::<Self>().hash(state);
+        self.service.hash(state);
+    }
+
+    fn stream(
+        self: Box<Self>,
+        _input: stream::BoxStream<iced_subscription::Event>,
+    ) -> stream::BoxStream<Self::Output> {
+        let (service, mut rx) = (self.service, self.rx);
+
+        // Unlike frames, no state change may be skipped; after a lag the
+        // next received state is still current
+        let s = async_stream::stream! {
+            loop {
+                match rx.recv().await {
+                    Ok(state) => yield (service, state),
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        };
+        Box::pin(s)
+    }
+}
@@ -1,10 +1,24 @@
 mod cam_service;
 mod cam_worker;
+mod capture;
+mod replay_worker;
 
 use std::sync::Arc;
+use std::time::Duration;
 
 pub use cam_service::{CameraManager, RgbaBuffer};
+pub use capture::CaptureSummary;
 
 /// RGBA frame sent to the UI
 /// (width, height, RgbaBuffer { frame, pool-pointer })
-pub type Frame = (u32, u32, Arc<RgbaBuffer>);
\ No newline at end of file
+pub type Frame = (u32, u32, Arc<RgbaBuffer>);
+
+/// Device connection changes published by the camera worker
+#[derive(Debug, Clone, PartialEq)]
+pub enum CameraEvent {
+    Connected { width: u32, height: u32 },
+    /// A single grab failed; the camera is still considered connected
+    CaptureError(String),
+    Disconnected(String),
+    Reconnecting { attempt: u32, delay: Duration },
+}
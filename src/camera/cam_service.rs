@@ -1,12 +1,20 @@
-use std::sync::atomic::Ordering;
 use std::error::Error;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tokio::sync::broadcast;
 
 use crate::SharedFrame;
 use crate::config::CameraConfig;
 use crate::utils::{ManagedService, ServiceCore};
 
-use super::{ Frame, cam_worker::CameraWorker };
+use super::{
+    CameraEvent, Frame,
+    cam_worker::CameraWorker,
+    capture::{CaptureRecorder, CaptureSummary},
+    replay_worker::ReplayWorker,
+};
 
 #[derive(Debug)]
 pub struct RgbaBuffer {
@@ -26,6 +34,9 @@ pub struct CameraManager {
     config: CameraConfig,
     core: ServiceCore<Frame>,
     shared: SharedFrame,
+    events: broadcast::Sender<CameraEvent>,
+    /// Set while raw frames are being written
+    capture: Arc<Mutex<Option<CaptureRecorder>>>,
 }
 
 impl CameraManager {
@@ -33,9 +44,50 @@ impl CameraManager {
         Self {
             config,
             shared,
-            core: ServiceCore::new(2)
+            core: ServiceCore::new(2),
+            events: broadcast::channel(16).0,
+            capture: Arc::new(Mutex::new(None)),
         }
     } 
+
+    /// Connection events from now on
+    pub fn events(&self) -> broadcast::Receiver<CameraEvent> {
+        self.events.subscribe()
+    }
+
+    /// Frames come from a capture file, which ends
+    pub fn is_replay(&self) -> bool {
+        self.config.replay.is_some()
+    }
+
+    pub fn is_capturing(&self) -> bool {
+        self.capture.lock().unwrap().is_some()
+    }
+
+    /// Write every camera frame from now on to a new capture file
+    pub fn start_capture(&self) -> Result<PathBuf, Box<dyn Error>> {
+        let mut capture = self.capture.lock().unwrap();
+        if capture.is_some() {
+            return Err("Already capturing".into());
+        }
+        if self.config.replay.is_some() {
+            return Err("Capture needs a live camera, not a replay".into());
+        }
+
+        let dir = &self.config.capture.dir;
+        std::fs::create_dir_all(dir)?;
+        let millis = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+        let path = Path::new(dir).join(format!("capture_{millis}.frames"));
+
+        *capture = Some(CaptureRecorder::start(path.clone(), self.config.capture.compress)?);
+        Ok(path)
+    }
+
+    /// Close the capture file; blocks until queued frames are written
+    pub fn stop_capture(&self) -> Result<CaptureSummary, Box<dyn Error>> {
+        let capture = self.capture.lock().unwrap().take().ok_or("Not capturing")?;
+        capture.finish()
+    }
 }
 
 impl ManagedService for CameraManager {
@@ -46,13 +98,25 @@ impl ManagedService for CameraManager {
     }
 
     fn start(&self) -> Result<(), Box<dyn Error>> {
-        self.core.running.store(true, Ordering::SeqCst);
+        self.core.begin_start()?;
 
-        CameraWorker {
-            config: self.config.clone(),
-            core: self.core.clone(),
-            shared: self.shared.clone(),
-        }
-        .spawn()
+        let spawned = match &self.config.replay {
+            Some(replay) => ReplayWorker {
+                config: replay.clone(),
+                core: self.core.clone(),
+                shared: self.shared.clone(),
+                events: self.events.clone(),
+            }
+            .spawn(),
+            None => CameraWorker {
+                config: self.config.clone(),
+                core: self.core.clone(),
+                shared: self.shared.clone(),
+                events: self.events.clone(),
+                capture: self.capture.clone(),
+            }
+            .spawn(),
+        };
+        spawned.inspect_err(|e| self.core.fail(e.as_ref()))
     }
 }
\ No newline at end of file
@@ -1,48 +1,73 @@
-use ccap::{PropertyName, Provider};
+use ccap::{PropertyName, Provider, VideoFrame};
+use tokio::sync::broadcast;
 
 use std::sync::atomic::Ordering;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::error::Error;
 use std::sync::{Arc, Mutex};
 
 use crate::SharedFrame;
 use crate::camera::RgbaBuffer;
 use crate::config::CameraConfig;
-use crate::utils::ServiceCore;
+use crate::utils::{STOP_TIMEOUT, ServiceCore, sleep_while};
 
-use super::Frame;
+use super::{CameraEvent, Frame, capture::CaptureRecorder};
+
+/// A single blocking grab, kept well under the stop timeout
+const GRAB_TIMEOUT: Duration = Duration::from_millis(STOP_TIMEOUT.as_millis() as u64 / 4);
+
+/// No frame for this long counts as a failed grab
+const FRAME_TIMEOUT: Duration = Duration::from_secs(3);
 
 pub struct CameraWorker {
     pub config: CameraConfig,
     pub core: ServiceCore<Frame>,
     pub shared: SharedFrame,
+    pub events: broadcast::Sender<CameraEvent>,
+    pub capture: Arc<Mutex<Option<CaptureRecorder>>>,
+}
+
+/// Opens the device for RGBA capture and reads back the real resolution
+fn open_camera(device: &str) -> Result<(Provider, u32, u32), ccap::CcapError> {
+    let mut camera = Provider::with_device_name(device)?;
+    camera.set_pixel_format(ccap::PixelFormat::Rgba32)?;
+
+    let width = camera.get_property(PropertyName::Width)? as u32;
+    let height = camera.get_property(PropertyName::Height)? as u32;
+    Ok((camera, width, height))
 }
 
 impl CameraWorker {
     pub fn spawn(self) -> Result<(), Box<dyn Error>> {
-        let mut camera = Provider::with_device_name(&self.config.device)?;
-        camera.set_pixel_format(ccap::PixelFormat::Rgba32)?;
-
-        let width = camera.get_property(PropertyName::Width)? as u32;
-        let height = camera.get_property(PropertyName::Height)? as u32;
+        // Fail the start right away when the device is missing or busy
+        let (mut camera, mut width, mut height) = open_camera(&self.config.device)?;
         println!(
             "Camera started successfully, real resolution: {}x{}",
             width, height
         );
+        let _ = self.events.send(CameraEvent::Connected { width, height });
 
         let pool: Arc<Mutex<Vec<Vec<u8>>>> = Arc::new(Mutex::new(Vec::new()));
 
-        thread::spawn(move || {
-            let frame_len = (width * height * 4) as usize;
+        let core = self.core.clone();
+        core.spawn("camera", move || {
+            let mut failures = 0;
 
             while self.core.running.load(Ordering::SeqCst) {
-                match camera.grab_frame(3000) {
+                let error = match self.grab(&mut camera) {
                     Ok(Some(frame)) => {
+                        let grabbed = Instant::now();
+                        failures = 0;
+
                         let data = frame.data().unwrap();
+                        let frame_len = (width * height * 4) as usize;
                         let mut rgba = {
                             let mut pool = pool.lock().unwrap();
-                            pool.pop().unwrap_or_else(|| vec![0u8; frame_len])
+                            // Buffers from before a reconnect may have another size
+                            pool.pop()
+                                .filter(|buf| buf.len() == frame_len)
+                                .unwrap_or_else(|| vec![0u8; frame_len])
                         };
                         rgba.copy_from_slice(data);
 
@@ -54,22 +79,84 @@ impl CameraWorker {
                         let captured_frame: Frame =
                             (width, height, Arc::new(buf));
 
+                        if let Some(capture) = self.capture.lock().unwrap().as_ref() {
+                            capture.send(grabbed, captured_frame.clone());
+                        }
+
                         let mut slot = self.shared.lock().unwrap();
                         *slot = Some(captured_frame.clone());
 
                         let _ = self.core.tx.send(captured_frame);
+                        continue;
                     }
-                    Ok(None) => {
-                        eprintln!("Unable to capture frame");
-                        thread::sleep(Duration::from_millis(100));
-                    }
-                    Err(e) => {
-                        eprintln!("Camera capture failed: {}", e);
-                        thread::sleep(Duration::from_millis(100));
-                    }
+                    Ok(None) if !self.core.running.load(Ordering::SeqCst) => break,
+                    Ok(None) => "Timed out waiting for a frame".to_string(),
+                    Err(e) => e.to_string(),
+                };
+
+                failures += 1;
+                let _ = self.events.send(CameraEvent::CaptureError(error.clone()));
+
+                if failures < self.config.reconnect.failures {
+                    thread::sleep(Duration::from_millis(100));
+                    continue;
+                }
+
+                eprintln!("Camera disconnected: {error}");
+                let _ = self.events.send(CameraEvent::Disconnected(error));
+                // Release the device before trying to reopen it
+                drop(camera);
+
+                match self.reconnect()? {
+                    Some(opened) => (camera, width, height) = opened,
+                    None => return Ok(()),
                 }
+                failures = 0;
             }
-        });
-        Ok(())
+            Ok(())
+        })
     }
-}
\ No newline at end of file
+
+    /// Waits up to `FRAME_TIMEOUT` for a frame in short grabs, so a stalled
+    /// camera doesn't hold up a stop. `None` on timeout or once stopped.
+    fn grab(&self, camera: &mut Provider) -> Result<Option<VideoFrame>, ccap::CcapError> {
+        let deadline = Instant::now() + FRAME_TIMEOUT;
+        while self.core.running.load(Ordering::SeqCst) && Instant::now() < deadline {
+            if let Some(frame) = camera.grab_frame(GRAB_TIMEOUT.as_millis() as u32)? {
+                return Ok(Some(frame));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Reopens the device with exponential backoff. `None` when the service
+    /// was stopped first.
+    fn reconnect(&self) -> Result<Option<(Provider, u32, u32)>, String> {
+        let policy = self.config.reconnect;
+        let mut delay = Duration::from_millis(policy.initial_delay_ms);
+        let mut attempt = 0;
+
+        loop {
+            attempt += 1;
+            let _ = self.events.send(CameraEvent::Reconnecting { attempt, delay });
+
+            if !sleep_while(&self.core.running, delay) {
+                return Ok(None);
+            }
+
+            match open_camera(&self.config.device) {
+                Ok((camera, width, height)) => {
+                    println!("Camera reconnected after {attempt} attempts: {width}x{height}");
+                    let _ = self.events.send(CameraEvent::Connected { width, height });
+                    return Ok(Some((camera, width, height)));
+                }
+                Err(e) if policy.max_attempts.is_some_and(|max| attempt >= max) => {
+                    return Err(format!("Camera lost, gave up after {attempt} reconnect attempts: {e}"));
+                }
+                Err(e) => eprintln!("Camera reconnect attempt {attempt} failed: {e}"),
+            }
+
+            delay = (delay * 2).min(Duration::from_millis(policy.max_delay_ms));
+        }
+    }
+}
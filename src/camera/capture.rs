@@ -0,0 +1,313 @@
+//! Raw frame container for replay benchmarks.
+//!
+//! A file is the magic `WMBFRAME` and a little-endian u32 version, then per
+//! frame: u64 microseconds since the first frame, u32 width, u32 height,
+//! u8 encoding, u32 payload length and the payload. Pixels are stored as
+//! RGB24 since camera frames are opaque.
+
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use flate2::Compression;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+
+use super::Frame;
+
+const MAGIC: &[u8; 8] = b"WMBFRAME";
+const VERSION: u32 = 1;
+
+const ENCODING_RAW: u8 = 0;
+const ENCODING_ZLIB: u8 = 1;
+
+/// Largest width or height accepted when reading
+const MAX_FRAME_SIDE: u32 = 16384;
+
+/// Frames waiting to be written before new ones are dropped
+const QUEUE_FRAMES: usize = 8;
+
+/// Header of a frame read back from a capture
+#[derive(Debug, Clone, Copy)]
+pub struct RecordedFrame {
+    /// Since the first frame of the capture
+    pub at: Duration,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct CaptureSummary {
+    pub path: PathBuf,
+    pub frames: usize,
+    pub bytes: u64,
+    /// Frames that arrived while the queue was full
+    pub dropped: usize,
+}
+
+struct CaptureWriter {
+    out: BufWriter<File>,
+    compress: bool,
+    start: Option<Instant>,
+    frames: usize,
+    bytes: u64,
+}
+
+impl CaptureWriter {
+    fn create(path: &Path, compress: bool) -> Result<Self, Box<dyn Error>> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(MAGIC)?;
+        out.write_all(&VERSION.to_le_bytes())?;
+
+        Ok(Self { out, compress, start: None, frames: 0, bytes: 12 })
+    }
+
+    fn write(&mut self, captured: Instant, frame: &Frame) -> Result<(), Box<dyn Error>> {
+        let (width, height, rgba) = frame;
+        let start = *self.start.get_or_insert(captured);
+        let at = captured.saturating_duration_since(start).as_micros() as u64;
+
+        let rgb: Vec<u8> = rgba.data.chunks_exact(4).flat_map(|px| &px[..3]).copied().collect();
+        let compressed = if self.compress {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::fast());
+            encoder.write_all(&rgb)?;
+            Some(encoder.finish()?)
+        } else {
+            None
+        };
+        // Noise can grow under zlib; readers expect payloads no bigger than raw
+        let (encoding, payload) = match compressed {
+            Some(zlib) if zlib.len() < rgb.len() => (ENCODING_ZLIB, zlib),
+            _ => (ENCODING_RAW, rgb),
+        };
+
+        self.out.write_all(&at.to_le_bytes())?;
+        self.out.write_all(&width.to_le_bytes())?;
+        self.out.write_all(&height.to_le_bytes())?;
+        self.out.write_all(&[encoding])?;
+        self.out.write_all(&(payload.len() as u32).to_le_bytes())?;
+        self.out.write_all(&payload)?;
+
+        self.frames += 1;
+        self.bytes += 21 + payload.len() as u64;
+        Ok(())
+    }
+}
+
+/// Writes camera frames to a capture file on its own thread, so encoding
+/// doesn't hold up the camera
+#[derive(Debug)]
+pub struct CaptureRecorder {
+    tx: SyncSender<(Instant, Frame)>,
+    dropped: AtomicUsize,
+    handle: JoinHandle<Result<CaptureSummary, String>>,
+}
+
+impl CaptureRecorder {
+    pub fn start(path: PathBuf, compress: bool) -> Result<Self, Box<dyn Error>> {
+        let mut writer = CaptureWriter::create(&path, compress)?;
+        let (tx, rx) = mpsc::sync_channel::<(Instant, Frame)>(QUEUE_FRAMES);
+
+        let handle = thread::Builder::new()
+            .name("capture".to_string())
+            .spawn(move || {
+                for (captured, frame) in rx {
+                    writer.write(captured, &frame).map_err(|e| e.to_string())?;
+                }
+                writer.out.flush().map_err(|e| e.to_string())?;
+                Ok(CaptureSummary { path, frames: writer.frames, bytes: writer.bytes, dropped: 0 })
+            })?;
+
+        Ok(Self { tx, dropped: AtomicUsize::new(0), handle })
+    }
+
+    /// Queue a frame grabbed at `captured`. Never blocks the camera; the
+    /// frame is dropped and counted when the writer falls behind.
+    pub fn send(&self, captured: Instant, frame: Frame) {
+        // A failed writer reports its error from `finish`
+        if let Err(TrySendError::Full(_)) = self.tx.try_send((captured, frame)) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Write out queued frames and close the file
+    pub fn finish(self) -> Result<CaptureSummary, Box<dyn Error>> {
+        drop(self.tx);
+        let mut summary = self.handle.join().map_err(|_| "Capture thread panicked")??;
+        summary.dropped = self.dropped.into_inner();
+        Ok(summary)
+    }
+}
+
+/// Reads a capture file frame by frame
+pub struct CaptureReader {
+    input: BufReader<File>,
+}
+
+impl CaptureReader {
+    pub fn open(path: &str) -> Result<Self, Box<dyn Error>> {
+        let mut input = BufReader::new(File::open(path)?);
+
+        let mut magic = [0; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(format!("{path} is not a frame capture").into());
+        }
+        let version = read_u32(&mut input)?;
+        if version != VERSION {
+            return Err(format!("Unsupported capture version {version}").into());
+        }
+
+        Ok(Self { input })
+    }
+
+    /// Decode the next frame into `rgba`. `None` at the end of the file.
+    pub fn next_frame(&mut self, rgba: &mut Vec<u8>) -> Result<Option<RecordedFrame>, Box<dyn Error>> {
+        let mut at = [0; 8];
+        match self.input.read_exact(&mut at) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+
+        let width = read_u32(&mut self.input)?;
+        let height = read_u32(&mut self.input)?;
+        let mut encoding = [0; 1];
+        self.input.read_exact(&mut encoding)?;
+
+        // Check the header before allocating, a corrupt length could be huge
+        let expected = width as usize * height as usize * 3;
+        let len = read_u32(&mut self.input)? as usize;
+        if width > MAX_FRAME_SIDE || height > MAX_FRAME_SIDE || len > expected {
+            return Err(format!("Corrupt frame header: {width}x{height} with a {len} byte payload").into());
+        }
+        let mut payload = vec![0; len];
+        self.input.read_exact(&mut payload)?;
+
+        let rgb = match encoding[0] {
+            ENCODING_RAW => payload,
+            ENCODING_ZLIB => {
+                let mut rgb = Vec::with_capacity(expected);
+                // One byte over is enough to tell the frame is too big
+                ZlibDecoder::new(payload.as_slice()).take(expected as u64 + 1).read_to_end(&mut rgb)?;
+                rgb
+            }
+            other => return Err(format!("Unknown frame encoding {other}").into()),
+        };
+        if rgb.len() != expected {
+            return Err(format!("Frame has {} bytes, expected {width}x{height} RGB", rgb.len()).into());
+        }
+
+        rgba.clear();
+        rgba.extend(rgb.chunks_exact(3).flat_map(|px| [px[0], px[1], px[2], 255]));
+        Ok(Some(RecordedFrame {
+            at: Duration::from_micros(u64::from_le_bytes(at)),
+            width,
+            height,
+        }))
+    }
+}
+
+fn read_u32(input: &mut impl Read) -> Result<u32, std::io::Error> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::camera::RgbaBuffer;
+
+    /// Unique per test so they can run in parallel
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("wmb_capture_{}_{name}.bin", std::process::id()))
+    }
+
+    fn frame(width: u32, height: u32, seed: u8) -> Frame {
+        let data = (0..width * height)
+            .flat_map(|i| [seed.wrapping_add(i as u8), seed, i as u8 / 3, 255])
+            .collect();
+        (width, height, Arc::new(RgbaBuffer { data, pool: Arc::new(Mutex::new(Vec::new())) }))
+    }
+
+    fn round_trip(name: &str, compress: bool) {
+        let path = temp_path(name);
+        let frames = [frame(64, 48, 0), frame(64, 48, 100), frame(32, 20, 7)];
+
+        let start = Instant::now();
+        let mut writer = CaptureWriter::create(&path, compress).unwrap();
+        for (i, frame) in frames.iter().enumerate() {
+            writer.write(start + Duration::from_millis(40 * i as u64), frame).unwrap();
+        }
+        writer.out.flush().unwrap();
+        let raw_bytes = 12 + frames.iter().map(|(w, h, _)| 21 + (w * h * 3) as u64).sum::<u64>();
+        assert_eq!(writer.bytes < raw_bytes, compress);
+        drop(writer);
+
+        let mut reader = CaptureReader::open(path.to_str().unwrap()).unwrap();
+        let mut rgba = Vec::new();
+        for (i, (width, height, buf)) in frames.iter().enumerate() {
+            let recorded = reader.next_frame(&mut rgba).unwrap().unwrap();
+            assert_eq!(recorded.at, Duration::from_millis(40 * i as u64));
+            assert_eq!((recorded.width, recorded.height), (*width, *height));
+            assert_eq!(rgba, buf.data);
+        }
+        assert!(reader.next_frame(&mut rgba).unwrap().is_none());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn raw_round_trip() {
+        round_trip("raw", false);
+    }
+
+    #[test]
+    fn zlib_round_trip() {
+        round_trip("zlib", true);
+    }
+
+    #[test]
+    fn truncated_frame_is_an_error() {
+        let path = temp_path("truncated");
+        let mut writer = CaptureWriter::create(&path, false).unwrap();
+        writer.write(Instant::now(), &frame(4, 3, 0)).unwrap();
+        writer.out.flush().unwrap();
+        drop(writer);
+
+        let len = std::fs::metadata(&path).unwrap().len();
+        File::options().write(true).open(&path).unwrap().set_len(len - 5).unwrap();
+
+        let mut reader = CaptureReader::open(path.to_str().unwrap()).unwrap();
+        assert!(reader.next_frame(&mut Vec::new()).is_err());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn oversized_payload_is_rejected() {
+        let path = temp_path("oversized");
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(VERSION.to_le_bytes());
+        bytes.extend(0u64.to_le_bytes());
+        bytes.extend(2u32.to_le_bytes());
+        bytes.extend(2u32.to_le_bytes());
+        bytes.push(ENCODING_RAW);
+        bytes.extend(u32::MAX.to_le_bytes());
+        std::fs::write(&path, bytes).unwrap();
+
+        let mut reader = CaptureReader::open(path.to_str().unwrap()).unwrap();
+        let error = reader.next_frame(&mut Vec::new()).unwrap_err();
+        assert!(error.to_string().contains("Corrupt frame header"), "{error}");
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
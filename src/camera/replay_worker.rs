@@ -0,0 +1,88 @@
+use tokio::sync::broadcast;
+
+use std::error::Error;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::SharedFrame;
+use crate::camera::RgbaBuffer;
+use crate::config::{ReplayConfig, ReplayTiming};
+use crate::utils::{ServiceCore, sleep_while};
+
+use super::{CameraEvent, Frame, capture::CaptureReader};
+
+/// Feeds a capture file through the same path as live camera frames
+pub struct ReplayWorker {
+    pub config: ReplayConfig,
+    pub core: ServiceCore<Frame>,
+    pub shared: SharedFrame,
+    pub events: broadcast::Sender<CameraEvent>,
+}
+
+impl ReplayWorker {
+    pub fn spawn(self) -> Result<(), Box<dyn Error>> {
+        // Fail the start right away when the file is missing or not a capture
+        let mut reader = CaptureReader::open(&self.config.path)?;
+        println!("Replaying {} ({:?} timing)", self.config.path, self.config.timing);
+
+        let pool: Arc<Mutex<Vec<Vec<u8>>>> = Arc::new(Mutex::new(Vec::new()));
+
+        let core = self.core.clone();
+        core.spawn("replay", move || {
+            let start = Instant::now();
+            let mut frames = 0;
+            let mut connected = false;
+
+            while self.core.running.load(Ordering::SeqCst) {
+                let mut rgba = pool.lock().unwrap().pop().unwrap_or_default();
+                let Some(recorded) = reader.next_frame(&mut rgba).map_err(|e| e.to_string())? else {
+                    println!("Replay finished after {frames} frames");
+                    break;
+                };
+
+                if !connected {
+                    let (width, height) = (recorded.width, recorded.height);
+                    let _ = self.events.send(CameraEvent::Connected { width, height });
+                    connected = true;
+                }
+
+                let on_time = match self.config.timing {
+                    ReplayTiming::Original => {
+                        let due = (start + recorded.at).saturating_duration_since(Instant::now());
+                        sleep_while(&self.core.running, due)
+                    }
+                    ReplayTiming::Fast => self.wait_for_taken(),
+                };
+                if !on_time {
+                    break;
+                }
+
+                let buf = RgbaBuffer {
+                    data: rgba,
+                    pool: pool.clone(),
+                };
+                let replayed_frame: Frame = (recorded.width, recorded.height, Arc::new(buf));
+
+                let mut slot = self.shared.lock().unwrap();
+                *slot = Some(replayed_frame.clone());
+
+                let _ = self.core.tx.send(replayed_frame);
+                frames += 1;
+            }
+            Ok(())
+        })
+    }
+
+    /// Waits until the model took the last frame. False when stopped first.
+    fn wait_for_taken(&self) -> bool {
+        while self.core.running.load(Ordering::SeqCst) {
+            if self.shared.lock().unwrap().is_none() {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        false
+    }
+}
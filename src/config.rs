@@ -1,12 +1,15 @@
 use serde::{Deserialize, Serialize};
 use std::{fs, path::Path};
 
-use crate::cv::InfType;
+use crate::cv::{InfType, TaskSettings};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
     pub model: ModelConfig,
     pub camera: CameraConfig,
+    pub export: Option<ExportConfig>,
+    #[serde(default)]
+    pub recording: RecordingConfig,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -15,23 +18,530 @@ pub struct ModelConfig {
     pub inference_type: InfType,
     pub generics: InferenceGenericConfig,
     pub pose: Option<PoseConfig>,
+    pub tracking: Option<TrackingConfig>,
+    pub object: Option<ObjectConfig>,
+    /// Person detector run before the pose model (top-down pose)
+    pub detector: Option<DetectorConfig>,
+    pub classify: Option<ClassifyConfig>,
+    pub depth: Option<DepthConfig>,
+    pub face: Option<FaceConfig>,
+    /// Throughput mode: run several images per inference
+    pub batch: Option<BatchConfig>,
+    /// Dummy inferences run after loading, before any frame is timed
+    #[serde(default)]
+    pub warmup_runs: usize,
+    #[serde(default)]
+    pub benchmark: BenchmarkConfig,
+}
+
+/// Fixed-input benchmark: repeated inference on a single image
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct BenchmarkConfig {
+    pub iterations: usize,
+    /// Image file to run; unset uses the current camera frame
+    pub image: Option<String>,
+}
+
+impl Default for BenchmarkConfig {
+    fn default() -> Self {
+        Self {
+            iterations: 100,
+            image: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct BatchConfig {
+    pub size: usize,
+    #[serde(default)]
+    pub source: BatchSource,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+pub enum BatchSource {
+    /// Consecutive camera frames; the overlay updates once per batch. A
+    /// paused or frozen frame is run as copies.
+    #[default]
+    Frames,
+    /// Copies of the current frame
+    Copies,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct ObjectConfig {
+    pub format: BoxFormat,
+    /// How `Detr` logits become class scores
+    pub scoring: DetrScoring,
+    /// The last `Detr` logit is the no-object class and never reported
+    pub no_object_class: bool,
+    /// `Detr` box output; by default the one whose name contains "box"
+    pub boxes_output: Option<String>,
+    /// `Detr` logits output, when only this one has a telling name
+    pub logits_output: Option<String>,
+}
+
+/// Output layout of a box detector
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+pub enum BoxFormat {
+    /// `[1, 4 + C, N]` axis-aligned boxes. Outputs in an `ExportedNms`
+    /// layout are detected and decoded as such.
+    #[default]
+    Yolo,
+    /// Exports with NMS in the graph: `boxes`, `scores` and `classes` (or
+    /// `labels`) outputs with an optional `num_dets`, `[N, 7]` rows of batch,
+    /// x1, y1, x2, y2, score, class, or `[1, K, 6]` rows without the batch
+    /// column. Boxes are in frame pixels when an `ImageSize` or `ScaleFactor`
+    /// input is configured, model input pixels otherwise.
+    ExportedNms,
+    /// `[1, 4 + C + 1, N]` rotated boxes, angle last
+    YoloObb,
+    /// NMS-free `[1, Q, 4]` normalized cxcywh boxes with `[1, Q, C]` logits
+    Detr,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+pub enum DetrScoring {
+    /// Independent per-class sigmoid (RT-DETR, Deformable DETR)
+    #[default]
+    Sigmoid,
+    /// Softmax over classes (original DETR, usually with `no_object_class`)
+    Softmax,
+    /// Outputs are already scores
+    Probabilities,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct FaceConfig {
+    pub format: FaceFormat,
+    /// Feature map strides, in output order. Defaults depend on `format`.
+    pub strides: Option<Vec<usize>>,
+    /// Anchors per feature map cell. Defaults depend on `format`.
+    pub anchors_per_stride: Option<usize>,
+}
+
+/// Output layout of an anchor-based face detector
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+pub enum FaceFormat {
+    /// score, bbox and kps outputs per stride; distances from the anchor point
+    #[default]
+    Scrfd,
+    /// cls, obj, bbox and kps outputs per stride; anchor-free offsets
+    Yunet,
+    /// Single regressors + classificators pair over SSD anchors
+    BlazeFace,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct DepthConfig {
+    pub colormap: Colormap,
+    /// Fixed `[min, max]` of the raw output; normalized per frame when unset
+    pub range: Option<[f32; 2]>,
+    /// Overlay opacity, 0..1
+    pub opacity: f32,
+}
+
+impl Default for DepthConfig {
+    fn default() -> Self {
+        Self {
+            colormap: Colormap::default(),
+            range: None,
+            opacity: 0.6,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+pub enum Colormap {
+    #[default]
+    Turbo,
+    Viridis,
+    Grayscale,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ClassifyConfig {
+    /// One label per line, or a JSON list / `{"0": "label"}` map
+    pub labels_path: Option<String>,
+    pub top_k: usize,
+    /// Apply softmax to raw logits
+    pub softmax: bool,
+}
+
+impl Default for ClassifyConfig {
+    fn default() -> Self {
+        Self {
+            labels_path: None,
+            top_k: 5,
+            softmax: true,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DetectorConfig {
+    pub model_path: String,
+    pub generics: InferenceGenericConfig,
+    #[serde(default)]
+    pub person_class: usize,
+    /// Person boxes are grown by this factor before cropping
+    #[serde(default = "default_box_padding")]
+    pub box_padding: f32,
+    pub object: Option<ObjectConfig>,
+}
+
+fn default_box_padding() -> f32 {
+    1.25
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct InferenceGenericConfig {
     pub inf_width: usize,
     pub inf_height: usize,
-    pub confidence_threshold: f32
+    pub confidence_threshold: f32,
+    #[serde(default = "default_iou_threshold")]
+    pub iou_threshold: f32,
+    /// Channel order the model expects; mean and std follow it
+    #[serde(default)]
+    pub channel_order: ChannelOrder,
+    /// Per-channel normalization of the 0..1 input, `(x - mean) / std`
+    #[serde(default = "default_mean")]
+    pub mean: [f32; 3],
+    #[serde(default = "default_std")]
+    pub std: [f32; 3],
+    /// Quantization of the image for u8/i8-input models
+    pub input_quant: Option<QuantConfig>,
+    /// Dequantization of every u8/i8 output, `x = (q - zero_point) * scale`
+    pub output_quant: Option<QuantConfig>,
+    /// Output decoded by single-tensor tasks; the model's first output when unset
+    pub output: Option<String>,
+    /// Values fed to each named model input. When empty the image goes to
+    /// the first input.
+    #[serde(default)]
+    pub inputs: Vec<InputConfig>,
+    /// Keypoint indices left out of the overlay
+    #[serde(default)]
+    pub hidden_keypoints: Vec<usize>,
+    /// Class ids left out of the overlay
+    #[serde(default)]
+    pub hidden_classes: Vec<usize>,
+    #[serde(default)]
+    pub overlay: OverlayConfig,
+}
+
+/// Look of the rendered overlay. Sizes are in pixels at `reference_height`
+/// and scale with the frame height.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct OverlayConfig {
+    pub reference_height: u32,
+    pub line_width: f32,
+    pub point_radius: f32,
+    pub font_size: f32,
+    /// Scores next to boxes
+    pub show_confidence: bool,
+    pub show_keypoint_names: bool,
+    pub show_keypoint_confidence: bool,
+    /// RGB per skeleton limb; unset limbs use the person's color
+    pub limb_colors: Vec<[u8; 3]>,
+    /// RGB per keypoint or face landmark index
+    pub keypoint_colors: Vec<[u8; 3]>,
+    /// RGB per class id, cycled; untracked boxes only, tracked ones keep
+    /// their track color
+    pub class_colors: Vec<[u8; 3]>,
+    /// RGB of text drawn on `label_background`
+    pub text_color: [u8; 3],
+    /// RGBA behind labels and classification bars
+    pub label_background: [u8; 4],
+}
+
+impl Default for OverlayConfig {
+    fn default() -> Self {
+        Self {
+            reference_height: 720,
+            line_width: 2.0,
+            point_radius: 4.0,
+            font_size: 16.0,
+            show_confidence: true,
+            show_keypoint_names: false,
+            show_keypoint_confidence: false,
+            limb_colors: Vec::new(),
+            keypoint_colors: Vec::new(),
+            class_colors: Vec::new(),
+            text_color: [255, 255, 255],
+            label_background: [0, 0, 0, 160],
+        }
+    }
+}
+
+/// Affine quantization, `q = x / scale + zero_point`
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct QuantConfig {
+    pub scale: f32,
+    pub zero_point: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct InputConfig {
+    pub name: String,
+    #[serde(flatten)]
+    pub source: InputSource,
+}
+
+/// What a model input is generated from
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "source")]
+pub enum InputSource {
+    /// The preprocessed `[1, 3, h, w]` image
+    Image,
+    /// `[1, 2]` size of the frame (or crop) before resizing, e.g. RT-DETR's
+    /// `orig_target_sizes`
+    ImageSize {
+        #[serde(default)]
+        order: SizeOrder,
+        #[serde(default)]
+        dtype: InputDtype,
+    },
+    /// `[1, 2]` input size / original size, e.g. PaddleDetection's `scale_factor`
+    ScaleFactor {
+        #[serde(default)]
+        order: SizeOrder,
+    },
+    Constant {
+        values: Vec<f32>,
+        shape: Vec<usize>,
+        #[serde(default)]
+        dtype: InputDtype,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+pub enum ChannelOrder {
+    #[default]
+    Rgb,
+    /// OpenCV-trained models such as YuNet
+    Bgr,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+pub enum SizeOrder {
+    #[default]
+    WidthHeight,
+    HeightWidth,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+pub enum InputDtype {
+    #[default]
+    Float32,
+    Int64,
+}
+
+fn default_mean() -> [f32; 3] {
+    [0.0; 3]
+}
+
+fn default_std() -> [f32; 3] {
+    [1.0; 3]
+}
+
+fn default_iou_threshold() -> f32 {
+    0.45
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct PoseConfig {
     pub keep_keypoints: [usize; 5],
+    pub smoothing: Option<SmoothingConfig>,
+    #[serde(default)]
+    pub heatmap_decoding: HeatmapDecoding,
+    /// Gaussian sigma (heatmap pixels) applied before decoding
+    pub heatmap_blur: Option<f32>,
+    /// Frames a keypoint is watched over before it's reported as mostly
+    /// below the confidence threshold
+    #[serde(default = "default_confidence_window")]
+    pub confidence_window: usize,
+}
+
+fn default_confidence_window() -> usize {
+    30
+}
+
+/// How a keypoint location is read off its heatmap
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+pub enum HeatmapDecoding {
+    /// Raw argmax on the heatmap grid
+    #[default]
+    Argmax,
+    /// Shift a quarter pixel toward the higher neighbour
+    QuarterOffset,
+    /// Taylor expansion of the log-heatmap around the maximum (DARK)
+    Dark,
+}
+
+/// One-Euro filter settings. With `beta = 0` this is plain exponential
+/// smoothing at `min_cutoff` Hz.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SmoothingConfig {
+    pub min_cutoff: f32,
+    pub beta: f32,
+    #[serde(default = "default_d_cutoff")]
+    pub d_cutoff: f32,
+    #[serde(default)]
+    pub keypoints: Vec<KeypointSmoothingConfig>,
+}
+
+/// Per-keypoint override of the filter settings, e.g. looser wrists.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct KeypointSmoothingConfig {
+    pub index: usize,
+    pub min_cutoff: Option<f32>,
+    pub beta: Option<f32>,
+}
+
+fn default_d_cutoff() -> f32 {
+    1.0
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct TrackingConfig {
+    /// Minimum IoU between a predicted track box and a detection to match
+    pub iou_threshold: f32,
+    /// Frames a track survives without a match
+    pub max_age: u32,
+    /// Matches needed before a track id is reported
+    pub min_hits: u32,
+    /// Detections below this score only extend existing tracks
+    pub high_score: f32,
+}
+
+impl Default for TrackingConfig {
+    fn default() -> Self {
+        Self {
+            iou_threshold: 0.3,
+            max_age: 30,
+            min_hits: 3,
+            high_score: 0.5,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct CameraConfig {
     pub device: String,
+    #[serde(default)]
+    pub reconnect: ReconnectConfig,
+    #[serde(default)]
+    pub capture: CaptureConfig,
+    /// Replay a capture file instead of opening the device
+    pub replay: Option<ReplayConfig>,
+}
+
+/// When to give up on a camera and how often to try reopening it
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct ReconnectConfig {
+    /// Consecutive failed grabs before the camera counts as disconnected
+    pub failures: u32,
+    pub initial_delay_ms: u64,
+    /// The delay doubles after each failed attempt, up to this
+    pub max_delay_ms: u64,
+    /// Unset retries until stopped
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        Self {
+            failures: 3,
+            initial_delay_ms: 500,
+            max_delay_ms: 8000,
+            max_attempts: None,
+        }
+    }
+}
+
+/// Raw camera frames written for later replay
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct CaptureConfig {
+    /// Output directory
+    pub dir: String,
+    /// Lossless zlib per frame; smaller files, more CPU while capturing
+    pub compress: bool,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self {
+            dir: "captures".to_string(),
+            compress: true,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReplayConfig {
+    /// Capture file written by "Capture Raw"
+    pub path: String,
+    #[serde(default)]
+    pub timing: ReplayTiming,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+pub enum ReplayTiming {
+    /// Frames arrive as far apart as they were captured, like a live camera
+    /// that keeps running while paused
+    #[default]
+    Original,
+    /// Each frame is sent as soon as the model took the previous one, so a
+    /// pause holds the replay
+    Fast,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExportConfig {
+    /// Per-frame results are appended here as JSON lines
+    pub results_path: String,
+}
+
+/// Snapshots and recordings of the annotated stream
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct RecordingConfig {
+    /// Output directory
+    pub dir: String,
+    pub format: RecordFormat,
+    /// Video frame rate; inferences are repeated or skipped to keep real time
+    pub fps: u32,
+    /// Encoder used for videos; it must be on the PATH or a full path
+    pub ffmpeg: String,
+}
+
+impl Default for RecordingConfig {
+    fn default() -> Self {
+        Self {
+            dir: "recordings".to_string(),
+            format: RecordFormat::default(),
+            fps: 30,
+            ffmpeg: "ffmpeg".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+pub enum RecordFormat {
+    /// One PNG per frame in a new directory
+    #[default]
+    Images,
+    /// H.264 MP4, piped through ffmpeg
+    Video,
 }
 
 impl Config {
@@ -46,4 +556,33 @@ impl Config {
         fs::write(path, toml)?;
         Ok(())
     }
+
+    /// Write live-tuned values into `[model.generics]` of the file at `path`.
+    /// Unlike `save`, the rest of the file is kept as written, comments included.
+    pub fn save_task_settings<P: AsRef<Path>>(path: P, settings: &TaskSettings) -> Result<(), Box<dyn std::error::Error>> {
+        let mut doc: toml_edit::DocumentMut = fs::read_to_string(&path)?.parse()?;
+        let generics = doc["model"]["generics"]
+            .as_table_like_mut()
+            .ok_or("No [model.generics] table")?;
+
+        // Through the shortest f32 representation, so 0.4 isn't written as 0.4000000059604645
+        let float = |v: f32| toml_edit::value(v.to_string().parse::<f64>().unwrap_or(v as f64));
+        generics.insert("confidence_threshold", float(settings.confidence_threshold));
+        generics.insert("iou_threshold", float(settings.iou_threshold));
+
+        for (key, ids) in [
+            ("hidden_keypoints", &settings.hidden_keypoints),
+            ("hidden_classes", &settings.hidden_classes),
+        ] {
+            if ids.is_empty() {
+                generics.remove(key);
+            } else {
+                let array: toml_edit::Array = ids.iter().map(|&id| id as i64).collect();
+                generics.insert(key, toml_edit::value(array));
+            }
+        }
+
+        fs::write(path, doc.to_string())?;
+        Ok(())
+    }
 }
\ No newline at end of file
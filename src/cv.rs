@@ -1,18 +1,51 @@
+mod benchmark;
 mod cv_inference;
 mod cv_service;
 mod cv_worker;
+mod export;
+mod freeze;
+mod recorder;
 mod tasks;
+mod top_down;
+mod tracking;
 
 use std::time::Duration;
 
 use crate::camera::Frame;
+pub use benchmark::{BenchmarkReport, TimingStats};
 pub use cv_service::CVManager;
+pub use recorder::{Recorder, RecordingSummary};
+pub use tasks::{COCO_KEYPOINT_NAMES, ConfidenceStats, JitterStats, KeypointConfidence, TaskResult, TaskSettings, TensorSummary};
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug)]
 pub struct Inference { 
     pub frame: Frame,
     pub time_metrics: TimeMetrics,
+    pub jitter: Option<JitterStats>,
+    pub top_down: Option<TopDownMetrics>,
+    /// Output summaries from the raw task
+    pub tensors: Option<Vec<TensorSummary>>,
+    pub batch: Option<BatchMetrics>,
+    /// Camera frame the overlay belongs to; the frozen one while paused
+    pub source: Frame,
+    /// Low-confidence keypoint statistics of pose models
+    pub confidence: Option<ConfidenceStats>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ModelInfo {
+    pub load_time: Duration,
+    pub decoding: Option<String>,
+    pub warmup: Option<WarmupMetrics>,
+}
+
+/// Inference latency of the cold first run and of the warm runs after it
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct WarmupMetrics {
+    pub first: Duration,
+    /// Mean over the remaining warmup runs
+    pub steady: Option<Duration>,
 }
 
 #[derive(Clone, Debug, Copy, Serialize, Deserialize)]
@@ -20,12 +53,52 @@ pub enum InfType {
     Pose,
     BoundingBox,
     Segment,
+    Classify,
+    Depth,
+    Face,
+    /// Any model; only timings and output summaries are reported
+    Raw,
 }
 
-#[derive(Clone, Debug, Copy, Serialize, Deserialize)]
+#[derive(Clone, Debug, Copy, Default, Serialize, Deserialize)]
 pub struct TimeMetrics {
     pub preprocess: Duration,
     pub inference: Duration,
     pub postprocess: Duration,
     pub render: Duration,
-}
\ No newline at end of file
+}
+
+impl std::ops::AddAssign for TimeMetrics {
+    fn add_assign(&mut self, other: Self) {
+        self.preprocess += other.preprocess;
+        self.inference += other.inference;
+        self.postprocess += other.postprocess;
+        self.render += other.render;
+    }
+}
+
+/// Per-stage timings of a detector + pose pipeline
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct TopDownMetrics {
+    pub detector: TimeMetrics,
+    pub people: Vec<TimeMetrics>,
+}
+
+impl TopDownMetrics {
+    /// Sum over the detector and every person crop
+    pub fn total(&self) -> TimeMetrics {
+        let mut total = self.detector;
+        for person in &self.people {
+            total += *person;
+        }
+        total
+    }
+}
+
+/// One batched inference against its per-frame share
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct BatchMetrics {
+    pub size: usize,
+    pub inference: Duration,
+    pub per_frame: Duration,
+}
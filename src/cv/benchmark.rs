@@ -0,0 +1,110 @@
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::sync::oneshot;
+
+use crate::camera::Frame;
+use super::{TimeMetrics, cv_inference::Model};
+
+/// Distribution of one timing over the benchmark iterations
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct TimingStats {
+    pub mean: Duration,
+    pub std_dev: Duration,
+    pub min: Duration,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub max: Duration,
+    /// Standard deviation over mean; lower is more stable
+    pub cv: f64,
+}
+
+impl TimingStats {
+    fn from_samples(samples: &[Duration]) -> Self {
+        let mut sorted = samples.to_vec();
+        sorted.sort();
+
+        let n = sorted.len() as f64;
+        let secs: Vec<f64> = sorted.iter().map(Duration::as_secs_f64).collect();
+        let mean = secs.iter().sum::<f64>() / n;
+        let variance = secs.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / n;
+        let std_dev = variance.sqrt();
+
+        // Nearest rank
+        let percentile = |p: f64| sorted[((p * n).ceil() as usize).clamp(1, sorted.len()) - 1];
+
+        Self {
+            mean: Duration::from_secs_f64(mean),
+            std_dev: Duration::from_secs_f64(std_dev),
+            min: sorted[0],
+            p50: percentile(0.50),
+            p95: percentile(0.95),
+            max: sorted[sorted.len() - 1],
+            cv: if mean > 0.0 { std_dev / mean } else { 0.0 },
+        }
+    }
+}
+
+/// Timings of repeated inference on one fixed image
+#[derive(Clone, Debug, Serialize)]
+pub struct BenchmarkReport {
+    pub iterations: usize,
+    /// Image path, or the captured frame size
+    pub source: String,
+    pub inference: TimingStats,
+    /// Preprocess through render
+    pub total: TimingStats,
+}
+
+/// Where the worker sends the report of a requested benchmark
+pub type BenchmarkReply = oneshot::Sender<Result<BenchmarkReport, String>>;
+
+/// Decodes an image file to RGBA
+pub fn load_image(path: &str) -> Result<Frame, Box<dyn Error>> {
+    let image = image::open(path)?.into_rgba8();
+    let (width, height) = image.dimensions();
+
+    let buf = crate::camera::RgbaBuffer {
+        data: image.into_raw(),
+        pool: Default::default(),
+    };
+    Ok((width, height, std::sync::Arc::new(buf)))
+}
+
+/// Runs `frame` through the model `iterations` times. Cancelled once
+/// `running` is cleared, so a long benchmark doesn't hold up a stop.
+pub fn run(
+    model: &mut Model,
+    frame: &Frame,
+    source: String,
+    iterations: usize,
+    running: &AtomicBool,
+) -> Result<BenchmarkReport, Box<dyn Error>> {
+    if iterations == 0 {
+        return Err("Benchmark needs at least one iteration".into());
+    }
+
+    let (width, height, rgba) = frame;
+    let mut inference = Vec::with_capacity(iterations);
+    let mut total = Vec::with_capacity(iterations);
+
+    for _ in 0..iterations {
+        if !running.load(Ordering::SeqCst) {
+            return Err("Benchmark cancelled".into());
+        }
+        let TimeMetrics { preprocess, inference: infer, postprocess, render } =
+            model.process_rgba(&rgba.data, *width, *height)?.time_metrics;
+
+        inference.push(infer);
+        total.push(preprocess + infer + postprocess + render);
+    }
+
+    Ok(BenchmarkReport {
+        iterations,
+        source,
+        inference: TimingStats::from_samples(&inference),
+        total: TimingStats::from_samples(&total),
+    })
+}
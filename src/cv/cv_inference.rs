@@ -1,69 +1,461 @@
-use std::{error::Error, time::Instant};
+use std::{error::Error, time::{Duration, Instant}};
 
-use ort::{inputs, session::Session, value::TensorRef};
+use ndarray::{Array4, Axis, concatenate};
+use ort::{session::{Session, SessionInputValue}, tensor::TensorElementType, value::{DynValue, Tensor, TensorRef}};
 
-use crate::{config::ModelConfig, cv::{InfType, TimeMetrics, tasks::{PoseTask, VisionTask}}};
+use crate::{config::{BatchConfig, ChannelOrder, InferenceGenericConfig, InputConfig, InputDtype, InputSource, ModelConfig, QuantConfig, SizeOrder}, cv::{BatchMetrics, InfType, TimeMetrics, TopDownMetrics, WarmupMetrics, tasks::{BBox, ClassifyTask, ConfidenceStats, ConfidenceTracker, DepthTask, FaceTask, JitterStats, ObjectTask, PoseSmoother, PoseTask, RawTask, TaskResult, TaskSettings, VisionTask, normalize, swap_rb}, top_down::Detector, tracking::Tracker}};
 
+/// One ONNX session and the task that feeds and decodes it
 #[derive(Debug)]
-pub struct Model {
+pub struct Stage {
     session: Session,
     task: Box<dyn VisionTask + Send + Sync>,
-    input_name: String,
-    output_name: String,
+    inputs: Vec<InputConfig>,
+    /// Element type the model expects for the image
+    image_type: TensorElementType,
+    quant: Option<QuantConfig>,
+    /// The image input accepts any batch size
+    dynamic_batch: bool,
+    inf_width: usize,
+    inf_height: usize,
+    channel_order: ChannelOrder,
+    mean: [f32; 3],
+    std: [f32; 3],
 }
 
-impl Model {
-    pub fn from_config(config: &ModelConfig) -> ort::Result<Self> {
+impl Stage {
+    pub fn new(
+        model_path: &str,
+        generics: &InferenceGenericConfig,
+        mut task: Box<dyn VisionTask + Send + Sync>,
+    ) -> Result<Self, Box<dyn Error>> {
         let session = Session::builder()?
-            .commit_from_file(&config.model_path)?;
+            .commit_from_file(model_path)?;
 
-        let task: Box<dyn VisionTask + Send + Sync> =
-            match config.inference_type {
-                InfType::Pose => Box::new(PoseTask::new(&config.generics, config.pose.as_ref().unwrap())),
-                InfType::BoundingBox => todo!(),
-                InfType::Segment => todo!(),
-            };
+        let inputs = if generics.inputs.is_empty() {
+            vec![InputConfig {
+                name: session.inputs()[0].name().to_string(),
+                source: InputSource::Image,
+            }]
+        } else {
+            generics.inputs.clone()
+        };
 
-        let input_name = session.inputs()[0].name().to_string();
-        let output_name = session.outputs()[0].name().to_string();
+        let mut image_type = TensorElementType::Float32;
+        let mut dynamic_batch = false;
+        for input in &inputs {
+            let Some(outlet) = session.inputs().iter().find(|i| i.name() == input.name) else {
+                return Err(format!("Model has no input named {}", input.name).into());
+            };
+            if let InputSource::Image = input.source {
+                image_type = outlet.dtype().tensor_type().unwrap_or(TensorElementType::Float32);
+                dynamic_batch = outlet.dtype().tensor_shape().and_then(|s| s.first().copied()).is_some_and(|d| d < 0);
+            }
+        }
+        if let Some(output) = &generics.output
+            && !session.outputs().iter().any(|o| o.name() == output)
+        {
+            return Err(format!("Model has no output named {output}").into());
+        }
+        task.inspect_outputs(session.outputs())?;
 
-        Ok(Self { 
+        Ok(Self {
             session,
             task,
-            input_name,
-            output_name,
+            inputs,
+            image_type,
+            quant: generics.input_quant,
+            dynamic_batch,
+            inf_width: generics.inf_width,
+            inf_height: generics.inf_height,
+            channel_order: generics.channel_order,
+            mean: generics.mean,
+            std: generics.std,
         })
     }
 
-    pub fn process_rgba(
+    /// Run `region` of the frame through the model. Results are in region
+    /// coordinates, next to the number of candidates below the confidence
+    /// threshold; render time is left at zero.
+    pub fn run(
         &mut self,
         rgba: &[u8],
         width: u32,
         height: u32,
-    ) -> Result<(Vec<u8>, TimeMetrics), Box<dyn Error>> {
+        region: &BBox,
+    ) -> Result<(TaskResult, usize, TimeMetrics), Box<dyn Error>> {
+        self.run_batch(&[rgba], width, height, region)
+    }
+
+    /// `run` with the same region of each frame stacked into one batch.
+    /// Only the first frame is decoded.
+    pub fn run_batch(
+        &mut self,
+        frames: &[&[u8]],
+        width: u32,
+        height: u32,
+        region: &BBox,
+    ) -> Result<(TaskResult, usize, TimeMetrics), Box<dyn Error>> {
+        let (region_w, region_h) = (region.width().round() as u32, region.height().round() as u32);
+
         let t0 = Instant::now();
-        let input = self.task.preprocess(rgba, width, height);
+        let mut images: Vec<Array4<f32>> = frames
+            .iter()
+            .map(|rgba| {
+                let mut image = self.task.preprocess(rgba, width, height, region);
+                if self.channel_order == ChannelOrder::Bgr {
+                    swap_rb(&mut image);
+                }
+                normalize(&mut image, self.mean, self.std);
+                image
+            })
+            .collect();
+        let image = match images.len() {
+            1 => images.pop().unwrap(),
+            _ => concatenate(Axis(0), &images.iter().map(|i| i.view()).collect::<Vec<_>>())?,
+        };
+
+        let mut inputs: Vec<(&str, SessionInputValue)> = Vec::with_capacity(self.inputs.len());
+        for input in &self.inputs {
+            let value = match &input.source {
+                InputSource::Image => image_input(&image, self.image_type, self.quant)?,
+                source => generated_input(
+                    source,
+                    frames.len(),
+                    (region_w as f32, region_h as f32),
+                    (self.inf_width as f32, self.inf_height as f32),
+                )?
+                .into(),
+            };
+            inputs.push((&input.name, value));
+        }
         let preprocess = t0.elapsed();
 
         let t1 = Instant::now();
-        let outputs = self.session.run(
-            inputs![&self.input_name => TensorRef::from_array_view(&input)?]
-        )?;
+        let outputs = self.session.run(inputs)?;
         let inference = t1.elapsed();
 
         let t2 = Instant::now();
-        let result = self.task.postprocess(&outputs, &self.output_name, width, height)?;
+        let (result, discarded) = self.task.postprocess(&outputs, region_w, region_h)?;
         let postprocess = t2.elapsed();
 
-        let t3 = Instant::now();
-        let img = self.task.render(&result, width, height);
-        let render = t3.elapsed();
-
-        Ok((img, TimeMetrics {
+        Ok((result, discarded, TimeMetrics {
             preprocess,
-            postprocess,
             inference,
-            render
+            postprocess,
+            ..Default::default()
         }))
     }
-}
\ No newline at end of file
+}
+
+/// The normalized image in the model's input element type. Integer inputs
+/// are quantized as `x / scale + zero_point`, by default back to 0..255
+/// pixel values.
+fn image_input(
+    image: &Array4<f32>,
+    ty: TensorElementType,
+    quant: Option<QuantConfig>,
+) -> Result<SessionInputValue<'_>, Box<dyn Error>> {
+    let quantize = |default_zero_point: i32, min: i32, max: i32| {
+        let q = quant.unwrap_or(QuantConfig { scale: 1.0 / 255.0, zero_point: default_zero_point });
+        image.mapv(move |v| ((v / q.scale).round() as i32 + q.zero_point).clamp(min, max))
+    };
+
+    Ok(match ty {
+        TensorElementType::Float32 => TensorRef::from_array_view(image)?.into(),
+        TensorElementType::Float16 => Tensor::from_array(image.mapv(half::f16::from_f32))?.into(),
+        TensorElementType::Uint8 => Tensor::from_array(quantize(0, 0, 255).mapv(|q| q as u8))?.into(),
+        TensorElementType::Int8 => Tensor::from_array(quantize(-128, -128, 127).mapv(|q| q as i8))?.into(),
+        other => return Err(format!("Unsupported image input type {other}").into()),
+    })
+}
+
+/// Tensor for a non-image input, given the batch size and the original and
+/// model input sizes
+fn generated_input(
+    source: &InputSource,
+    batch: usize,
+    (orig_w, orig_h): (f32, f32),
+    (inf_w, inf_h): (f32, f32),
+) -> ort::Result<DynValue> {
+    // One `[w, h]` or `[h, w]` row per batch item
+    let pairs = |order: &SizeOrder, w: f32, h: f32| match order {
+        SizeOrder::WidthHeight => [w, h].repeat(batch),
+        SizeOrder::HeightWidth => [h, w].repeat(batch),
+    };
+
+    let (shape, values, dtype) = match source {
+        InputSource::Image => unreachable!(),
+        InputSource::ImageSize { order, dtype } => (vec![batch, 2], pairs(order, orig_w, orig_h), *dtype),
+        InputSource::ScaleFactor { order } => {
+            (vec![batch, 2], pairs(order, inf_w / orig_w, inf_h / orig_h), InputDtype::Float32)
+        }
+        InputSource::Constant { values, shape, dtype } => (shape.clone(), values.clone(), *dtype),
+    };
+
+    Ok(match dtype {
+        InputDtype::Float32 => Tensor::from_array((shape, values))?.into_dyn(),
+        InputDtype::Int64 => {
+            let values: Vec<i64> = values.into_iter().map(|v| v as i64).collect();
+            Tensor::from_array((shape, values))?.into_dyn()
+        }
+    })
+}
+
+#[derive(Debug)]
+pub struct ModelOutput {
+    pub overlay: Vec<u8>,
+    pub time_metrics: TimeMetrics,
+    pub top_down: Option<TopDownMetrics>,
+    pub batch: Option<BatchMetrics>,
+    pub confidence: Option<ConfidenceStats>,
+    pub result: TaskResult,
+}
+
+#[derive(Debug)]
+pub struct Model {
+    stage: Stage,
+    detector: Option<Detector>,
+    tracker: Option<Tracker>,
+    smoother: Option<PoseSmoother>,
+    confidence: Option<ConfidenceTracker>,
+    batch: Option<BatchConfig>,
+    decoding: Option<String>,
+}
+
+impl Model {
+    pub fn from_config(config: &ModelConfig) -> Result<Self, Box<dyn Error>> {
+        let task: Box<dyn VisionTask + Send + Sync> =
+            match config.inference_type {
+                InfType::Pose => Box::new(PoseTask::new(&config.generics, config.pose.as_ref().unwrap())),
+                InfType::BoundingBox => Box::new(ObjectTask::new(
+                    &config.generics,
+                    &config.object.clone().unwrap_or_default(),
+                )),
+                InfType::Segment => todo!(),
+                InfType::Classify => Box::new(ClassifyTask::new(
+                    &config.generics,
+                    &config.classify.clone().unwrap_or_default(),
+                )?),
+                InfType::Depth => Box::new(DepthTask::new(
+                    &config.generics,
+                    &config.depth.clone().unwrap_or_default(),
+                )),
+                InfType::Face => Box::new(FaceTask::new(
+                    &config.generics,
+                    &config.face.clone().unwrap_or_default(),
+                )),
+                InfType::Raw => Box::new(RawTask::new(&config.generics)),
+            };
+
+        let smoother = match config.inference_type {
+            InfType::Pose => config.pose.as_ref()
+                .and_then(|p| p.smoothing.as_ref())
+                .map(|s| PoseSmoother::new(s, config.generics.confidence_threshold)),
+            _ => None,
+        };
+
+        // Tasks that drop candidates below the threshold; the window only
+        // matters for keypoints
+        let confidence = match config.inference_type {
+            InfType::Pose => config.pose.as_ref()
+                .map(|p| ConfidenceTracker::new(p.confidence_window, config.generics.confidence_threshold)),
+            InfType::BoundingBox | InfType::Face => {
+                Some(ConfidenceTracker::new(1, config.generics.confidence_threshold))
+            }
+            _ => None,
+        };
+
+        let detector = match (config.inference_type, config.detector.as_ref()) {
+            (InfType::Pose, Some(detector)) => {
+                let aspect = config.generics.inf_width as f32 / config.generics.inf_height as f32;
+                Some(Detector::from_config(detector, aspect)?)
+            }
+            _ => None,
+        };
+
+        let tracker = config.tracking.as_ref().map(Tracker::new);
+        let stage = Stage::new(&config.model_path, &config.generics, task)?;
+        let decoding = stage.task.decoding();
+
+        if let Some(batch) = &config.batch {
+            if batch.size == 0 {
+                return Err("Batch size must be at least 1".into());
+            }
+            if detector.is_some() {
+                return Err("Batch mode is not supported with a detector stage".into());
+            }
+            if batch.size > 1 && !stage.dynamic_batch {
+                return Err("Batch mode needs a model with a dynamic batch dimension".into());
+            }
+        }
+
+        Ok(Self {
+            stage,
+            detector,
+            tracker,
+            smoother,
+            confidence,
+            batch: config.batch,
+            decoding,
+        })
+    }
+
+    /// Clear per-session state carried across frames
+    pub fn reset(&mut self) {
+        if let Some(tracker) = self.tracker.as_mut() {
+            tracker.reset();
+        }
+        if let Some(smoother) = self.smoother.as_mut() {
+            smoother.reset();
+        }
+        if let Some(confidence) = self.confidence.as_mut() {
+            confidence.reset();
+        }
+    }
+
+    pub fn decoding(&self) -> Option<&str> {
+        self.decoding.as_deref()
+    }
+
+    /// Update the main task's thresholds and overlay filters; the detector
+    /// stage keeps its own
+    pub fn apply_settings(&mut self, settings: &TaskSettings) {
+        self.stage.task.apply_settings(settings);
+        if let Some(smoother) = self.smoother.as_mut() {
+            smoother.set_threshold(settings.confidence_threshold);
+        }
+        if let Some(confidence) = self.confidence.as_mut() {
+            confidence.set_threshold(settings.confidence_threshold);
+        }
+    }
+
+    pub fn jitter(&self) -> Option<JitterStats> {
+        self.smoother.as_ref().and_then(|s| s.jitter())
+    }
+
+    /// Run `runs` inferences on a blank model-sized frame so lazy
+    /// allocations and kernel selection happen before real frames are timed
+    pub fn warmup(&mut self, runs: usize) -> Result<Option<WarmupMetrics>, Box<dyn Error>> {
+        let (width, height) = (self.stage.inf_width as u32, self.stage.inf_height as u32);
+        let blank = vec![0u8; (width * height * 4) as usize];
+        let frames = vec![blank.as_slice(); self.batch.map_or(1, |b| b.size)];
+
+        let mut latencies = Vec::with_capacity(runs);
+        for _ in 0..runs {
+            let output = self.process_batch(&frames, width, height)?;
+            let mut latency = output.time_metrics.inference;
+
+            // A blank frame has no people in it, so warm the pose stage directly
+            if self.detector.is_some() {
+                let (_, _, metrics) = self.stage.run(&blank, width, height, &BBox::frame(width, height))?;
+                latency += metrics.inference;
+            }
+            latencies.push(latency);
+        }
+        self.reset();
+
+        let Some((&first, rest)) = latencies.split_first() else {
+            return Ok(None);
+        };
+        let steady = (!rest.is_empty()).then(|| rest.iter().sum::<Duration>() / rest.len() as u32);
+
+        Ok(Some(WarmupMetrics { first, steady }))
+    }
+
+    pub fn batch(&self) -> Option<BatchConfig> {
+        self.batch
+    }
+
+    pub fn process_rgba(
+        &mut self,
+        rgba: &[u8],
+        width: u32,
+        height: u32,
+    ) -> Result<ModelOutput, Box<dyn Error>> {
+        self.process_batch(&[rgba], width, height)
+    }
+
+    /// Run `frames` as one batch. The first frame is decoded and rendered.
+    pub fn process_batch(
+        &mut self,
+        frames: &[&[u8]],
+        width: u32,
+        height: u32,
+    ) -> Result<ModelOutput, Box<dyn Error>> {
+        let (mut result, discarded, mut time_metrics, top_down) = match self.detector.as_mut() {
+            Some(detector) => {
+                let (result, discarded, metrics) = detector.run(&mut self.stage, frames[0], width, height)?;
+                (result, discarded, metrics.total(), Some(metrics))
+            }
+            None => {
+                let (result, discarded, metrics) = self.stage.run_batch(frames, width, height, &BBox::frame(width, height))?;
+                (result, discarded, metrics, None)
+            }
+        };
+
+        let batch = (frames.len() > 1).then(|| BatchMetrics {
+            size: frames.len(),
+            inference: time_metrics.inference,
+            per_frame: time_metrics.inference / frames.len() as u32,
+        });
+
+        let t2 = Instant::now();
+        if let Some(tracker) = self.tracker.as_mut() {
+            assign_track_ids(tracker, &mut result);
+        }
+        // On the decoded keypoints, before smoothing
+        let confidence = match (self.confidence.as_mut(), &result) {
+            (Some(tracker), TaskResult::Pose(people)) => Some(tracker.update(people, discarded)),
+            (Some(tracker), _) => Some(tracker.update(&[], discarded)),
+            (None, _) => None,
+        };
+        if let (Some(smoother), TaskResult::Pose(people)) = (self.smoother.as_mut(), &mut result) {
+            smoother.apply(people, t2);
+        }
+        time_metrics.postprocess += t2.elapsed();
+
+        let t3 = Instant::now();
+        let overlay = self.stage.task.render(&result, width, height);
+        time_metrics.render = t3.elapsed();
+
+        Ok(ModelOutput {
+            overlay,
+            time_metrics,
+            top_down,
+            batch,
+            confidence,
+            result,
+        })
+    }
+}
+
+fn assign_track_ids(tracker: &mut Tracker, result: &mut TaskResult) {
+    match result {
+        TaskResult::Pose(people) => {
+            let detections: Vec<_> = people.iter().map(|p| p.detection()).collect();
+            for (person, id) in people.iter_mut().zip(tracker.update(&detections)) {
+                person.track_id = id;
+            }
+        }
+        TaskResult::Detections(detections) => {
+            let ids = tracker.update(detections);
+            for (det, id) in detections.iter_mut().zip(ids) {
+                det.track_id = id;
+            }
+        }
+        TaskResult::OrientedDetections(detections) => {
+            let boxes: Vec<_> = detections.iter().map(|d| d.detection()).collect();
+            for (det, id) in detections.iter_mut().zip(tracker.update(&boxes)) {
+                det.track_id = id;
+            }
+        }
+        TaskResult::Faces(faces) => {
+            let detections: Vec<_> = faces.iter().map(|f| f.detection()).collect();
+            for (face, id) in faces.iter_mut().zip(tracker.update(&detections)) {
+                face.track_id = id;
+            }
+        }
+        TaskResult::SegmentationMask(_) | TaskResult::Classes(_) | TaskResult::Depth(_) | TaskResult::Raw(_) => {}
+    }
+}
@@ -1,42 +1,134 @@
-use std::sync::atomic::Ordering;
-use std::time::Duration;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::path::{Path, PathBuf};
 use std::{time::Instant, error::Error};
 use std::sync::{Arc, Mutex};
 
 use crate::SharedFrame;
-use crate::config::ModelConfig;
+use crate::config::{ExportConfig, ModelConfig};
 use crate::cv::cv_worker::CVWorker;
-use crate::utils::{ManagedService, ServiceCore};
-use super::{Inference, cv_inference::Model};
+use crate::utils::{ManagedService, ServiceCore, ServiceState};
+use super::{BenchmarkReport, InfType, Inference, ModelInfo, Recorder, TaskSettings, benchmark::BenchmarkReply, cv_inference::Model, freeze::FreezeControl};
+
+use tokio::sync::{oneshot, watch};
 
 #[derive(Debug)]
 pub struct CVManager {
     config: ModelConfig,
+    export: Option<ExportConfig>,
     model: Arc<Mutex<Option<Model>>>,
     shared: SharedFrame,
     core: ServiceCore<Inference>,
+    /// Bumped to cancel loads in progress
+    load_generation: AtomicU64,
+    freeze: Arc<FreezeControl>,
+    /// Benchmark waiting for the worker to pick it up
+    benchmark: Arc<Mutex<Option<BenchmarkReply>>>,
+    /// Latest thresholds and filters, applied by the worker between frames
+    settings: watch::Sender<TaskSettings>,
+    /// Gets every inference on its own queue, so slow encoding can't lag
+    recorder: Arc<Recorder>,
 }
 
 impl CVManager {
-    pub fn new(config: ModelConfig, shared: SharedFrame) -> Self {
+    pub fn new(config: ModelConfig, export: Option<ExportConfig>, shared: SharedFrame, recorder: Arc<Recorder>) -> Self {
         Self {
+            settings: watch::Sender::new(TaskSettings::from(&config.generics)),
             config,
+            export,
             model: Arc::new(Mutex::new(None)),
             shared,
             core: ServiceCore::new(1),
+            load_generation: AtomicU64::new(0),
+            freeze: Arc::new(FreezeControl::default()),
+            benchmark: Arc::new(Mutex::new(None)),
+            recorder,
         }
     }
 
-    pub fn load_model(&self) -> Result<Duration, Box<dyn Error>> {
+    /// Blocks while the model loads and warms up; run it off the UI thread
+    pub fn load_model(&self) -> Result<ModelInfo, Box<dyn Error>> {
+        let generation = self.load_generation.load(Ordering::SeqCst);
+
         let now = Instant::now();
-        let estimator = Model::from_config(&self.config)?;
+        let mut estimator = Model::from_config(&self.config)?;
         let elapsed = now.elapsed();
 
+        let warmup = estimator.warmup(self.config.warmup_runs)?;
+
+        let info = ModelInfo {
+            load_time: elapsed,
+            decoding: estimator.decoding().map(str::to_string),
+            warmup,
+        };
+
         let mut model_lock = self.model.lock().unwrap();
+        if self.load_generation.load(Ordering::SeqCst) != generation {
+            return Err("Model loading cancelled".into());
+        }
         *model_lock = Some(estimator);
 
         println!("Loading model took {:?}", elapsed);
-        Ok(elapsed)
+        if let Some(decoding) = &info.decoding {
+            println!("Decoding: {decoding}");
+        }
+        if let Some(warmup) = &info.warmup {
+            println!("First inference took {:?}, warm inference {:?}", warmup.first, warmup.steady);
+        }
+        Ok(info)
+    }
+
+    /// Drop the result of any load in progress. Session creation can't be
+    /// interrupted, so the load still finishes in the background.
+    pub fn cancel_load(&self) {
+        self.load_generation.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.freeze.is_paused()
+    }
+
+    /// Freeze on the current frame and keep re-running inference on it
+    pub fn pause(&self) {
+        self.freeze.set_paused(true);
+    }
+
+    pub fn resume(&self) {
+        self.freeze.set_paused(false);
+    }
+
+    /// While paused, advance the frozen frame by one camera frame
+    pub fn step(&self) {
+        self.freeze.step();
+    }
+
+    /// Ask the running worker for a fixed-input benchmark. Live inference
+    /// pauses until the report arrives.
+    pub fn request_benchmark(&self) -> Result<oneshot::Receiver<Result<BenchmarkReport, String>>, Box<dyn Error>> {
+        if self.state() != ServiceState::Running {
+            return Err("Start the model before benchmarking".into());
+        }
+
+        let (reply, rx) = oneshot::channel();
+        *self.benchmark.lock().unwrap() = Some(reply);
+        Ok(rx)
+    }
+
+    pub fn inference_type(&self) -> InfType {
+        self.config.inference_type
+    }
+
+    pub fn settings(&self) -> TaskSettings {
+        self.settings.borrow().clone()
+    }
+
+    /// Push new thresholds and filters to the running task
+    pub fn update_settings(&self, settings: TaskSettings) {
+        self.settings.send_replace(settings);
+    }
+
+    /// Save the frozen frame as a PNG fixture in `dir`
+    pub fn save_frozen(&self, dir: &Path) -> Result<PathBuf, Box<dyn Error>> {
+        self.freeze.save_png(dir)
     }
 }
 
@@ -48,13 +140,20 @@ impl ManagedService for CVManager {
     }
 
     fn start(&self) -> Result<(), Box<dyn std::error::Error>> {
-        self.core.running.store(true, Ordering::SeqCst);
+        self.core.begin_start()?;
 
         CVWorker {
             model: self.model.clone(),
+            export: self.export.clone(),
             shared: self.shared.clone(),
             core: self.core.clone(),
+            freeze: self.freeze.clone(),
+            benchmark_config: self.config.benchmark.clone(),
+            benchmark: self.benchmark.clone(),
+            settings: self.settings.subscribe(),
+            recorder: self.recorder.clone(),
         }
         .spawn()
+        .inspect_err(|e| self.core.fail(e.as_ref()))
     }
 }
@@ -1,47 +1,105 @@
 use std::sync::atomic::Ordering;
 use std::time::Duration;
-use std::{thread, error::Error};
+use std::error::Error;
 use std::sync::{Arc, Mutex};
 
+use tokio::sync::watch;
+
 use crate::camera::RgbaBuffer;
 use crate::SharedFrame;
+use crate::config::{BatchConfig, BatchSource, BenchmarkConfig, ExportConfig};
 use crate::utils::ServiceCore;
-use super::{Inference, cv_inference::Model};
+use super::{BenchmarkReport, Inference, Recorder, TaskResult, TaskSettings, benchmark::{self, BenchmarkReply}, cv_inference::Model, export::ResultsWriter, freeze::FreezeControl};
 
 pub struct CVWorker {
     pub model: Arc<Mutex<Option<Model>>>,
+    pub export: Option<ExportConfig>,
     pub shared: SharedFrame,
-    pub core: ServiceCore<Inference>
+    pub core: ServiceCore<Inference>,
+    pub freeze: Arc<FreezeControl>,
+    pub benchmark_config: BenchmarkConfig,
+    pub benchmark: Arc<Mutex<Option<BenchmarkReply>>>,
+    pub settings: watch::Receiver<TaskSettings>,
+    pub recorder: Arc<Recorder>,
 }
 
 impl CVWorker {
     pub fn spawn(self) -> Result<(), Box<dyn Error>> {
         let pool: Arc<Mutex<Vec<Vec<u8>>>> = Arc::new(Mutex::new(Vec::new()));
+        let mut writer = self.export.as_ref().map(ResultsWriter::open).transpose()?;
+
+        let core = self.core.clone();
+        core.spawn("cv", move || {
+            let mut settings = self.settings.clone();
 
-        thread::spawn(move || {
             // ---------- Get reference to Model inside thread ----------
             let mut model_lock = self.model.lock().unwrap();
 
-            let model = match model_lock.as_mut() {
-                Some(p) => p,
-                None => {
-                    eprintln!("Model not loaded!");
-                    return;
-                }
+            let Some(model) = model_lock.as_mut() else {
+                return Err("Model not loaded".to_string());
             };
+            model.reset();
+            // The model was built from the config; catch up on live changes
+            model.apply_settings(&settings.borrow_and_update());
+
+            // Frames waiting to fill a batch with their size, oldest first
+            let mut pending: Vec<(u32, u32, Vec<u8>)> = Vec::new();
 
             while self.core.running.load(Ordering::SeqCst) {
-                let frame_opt = {
+                if settings.has_changed().unwrap_or(false) {
+                    model.apply_settings(&settings.borrow_and_update());
+                }
+
+                let request = self.benchmark.lock().unwrap().take();
+                if let Some(reply) = request {
+                    let report = self.run_benchmark(model).map_err(|e| e.to_string());
+                    // Tracking state saw the same frame over and over
+                    model.reset();
+                    pending.clear();
+                    let _ = reply.send(report);
+                    continue;
+                }
+
+                let frame_opt = if self.freeze.wants_frame() {
                     let mut slot = self.shared.lock().unwrap();
                     slot.take() // take() = replace with None
+                } else {
+                    None
                 };
 
-                if let Some(frame) = frame_opt {
+                if let Some((frame, rerun)) = self.freeze.next_frame(frame_opt) {
                     // ---------- Extract RGBA ----------
                     let (width, height, rgba) = (frame.0, frame.1, frame.2.data.clone());
 
                     // ---------- Inference ----------
-                    let (output, time_metrics) = match model.process_rgba(&rgba, width, height) {
+                    let result = match model.batch() {
+                        None => model.process_rgba(&rgba, width, height),
+                        Some(BatchConfig { size, source: BatchSource::Copies }) => {
+                            model.process_batch(&vec![rgba.as_slice(); size], width, height)
+                        }
+                        // A paused or frozen frame isn't new; fill the batch with it
+                        Some(BatchConfig { size, source: BatchSource::Frames }) if rerun => {
+                            pending.clear();
+                            model.process_batch(&vec![rgba.as_slice(); size], width, height)
+                        }
+                        Some(BatchConfig { size, source: BatchSource::Frames }) => {
+                            // Older frames can't share a batch after a resolution change
+                            if pending.first().is_some_and(|(w, h, _)| (*w, *h) != (width, height)) {
+                                pending.clear();
+                            }
+                            pending.push((width, height, rgba));
+                            if pending.len() < size {
+                                continue;
+                            }
+                            // Newest first, so the decoded frame is the one on screen
+                            let frames: Vec<&[u8]> = pending.iter().rev().map(|(_, _, rgba)| rgba.as_slice()).collect();
+                            let result = model.process_batch(&frames, width, height);
+                            pending.clear();
+                            result
+                        }
+                    };
+
+                    let output = match result {
                         Ok(o) => o,
                         Err(e) => {
                             eprintln!("Inference error: {e}");
@@ -49,20 +107,69 @@ impl CVWorker {
                         }
                     };
 
+                    let jitter = model.jitter();
+                    // Re-runs of a frozen frame would only repeat the last record
+                    if let Some(writer) = writer.as_mut().filter(|_| !rerun)
+                        && let Err(e) = writer.write(model.decoding(), &output, jitter)
+                    {
+                        eprintln!("Unable to export results: {e}");
+                    }
+
                     // ---------- Publish result ----------
+                    let tensors = match &output.result {
+                        TaskResult::Raw(summaries) => Some(summaries.clone()),
+                        _ => None,
+                    };
+
                     let buf = RgbaBuffer {
-                        data: output,
+                        data: output.overlay,
                         pool: pool.clone(),
                     };
 
-                    let _ = self.core.tx.send(Inference { frame: (width, height, Arc::new(buf)), time_metrics });
+                    let inference = Inference {
+                        frame: (width, height, Arc::new(buf)),
+                        time_metrics: output.time_metrics,
+                        jitter,
+                        top_down: output.top_down,
+                        tensors,
+                        batch: output.batch,
+                        source: frame.clone(),
+                        confidence: output.confidence,
+                    };
+                    self.recorder.push(&inference);
+                    let _ = self.core.tx.send(inference);
                 } else {
                     //No frame available, yield CPU
                     std::thread::sleep(Duration::from_millis(5));
                 }
             }
-        });
 
-        Ok(())
-    } 
+            // Nobody will answer a request that came in while stopping
+            self.benchmark.lock().unwrap().take();
+            Ok(())
+        })
+    }
+
+    /// Loops the model on the configured image, or the current frame
+    fn run_benchmark(&self, model: &mut Model) -> Result<BenchmarkReport, Box<dyn Error>> {
+        let (frame, source) = match &self.benchmark_config.image {
+            Some(path) => (benchmark::load_image(path)?, path.clone()),
+            None => {
+                let frame = self.freeze.current().ok_or("No camera frame captured yet")?;
+                let source = format!("camera frame {}x{}", frame.0, frame.1);
+                (frame, source)
+            }
+        };
+
+        let report = benchmark::run(model, &frame, source, self.benchmark_config.iterations, &self.core.running)?;
+        println!(
+            "Benchmark on {} x{}: inference {:?} mean, {:?} p95, CV {:.1}%",
+            report.source,
+            report.iterations,
+            report.inference.mean,
+            report.inference.p95,
+            report.inference.cv * 100.0,
+        );
+        Ok(report)
+    }
 }
\ No newline at end of file
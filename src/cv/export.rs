@@ -0,0 +1,63 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::error::Error;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::config::ExportConfig;
+use super::{BatchMetrics, ConfidenceStats, JitterStats, TimeMetrics, TopDownMetrics, cv_inference::ModelOutput, tasks::TaskResult};
+
+#[derive(Serialize)]
+struct ExportRecord<'a> {
+    frame: u64,
+    timestamp_ms: u128,
+    decoding: Option<&'a str>,
+    time_metrics: &'a TimeMetrics,
+    top_down: Option<&'a TopDownMetrics>,
+    batch: Option<BatchMetrics>,
+    jitter: Option<JitterStats>,
+    confidence: Option<&'a ConfidenceStats>,
+    result: &'a TaskResult,
+}
+
+/// Appends one JSON line per inference to the configured results file
+pub struct ResultsWriter {
+    out: BufWriter<File>,
+    frame: u64,
+}
+
+impl ResultsWriter {
+    pub fn open(config: &ExportConfig) -> Result<Self, Box<dyn Error>> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&config.results_path)?;
+
+        Ok(Self { out: BufWriter::new(file), frame: 0 })
+    }
+
+    pub fn write(
+        &mut self,
+        decoding: Option<&str>,
+        output: &ModelOutput,
+        jitter: Option<JitterStats>,
+    ) -> Result<(), Box<dyn Error>> {
+        let record = ExportRecord {
+            frame: self.frame,
+            timestamp_ms: SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis(),
+            decoding,
+            time_metrics: &output.time_metrics,
+            top_down: output.top_down.as_ref(),
+            batch: output.batch,
+            jitter,
+            confidence: output.confidence.as_ref(),
+            result: &output.result,
+        };
+        self.frame += 1;
+
+        serde_json::to_writer(&mut self.out, &record)?;
+        self.out.write_all(b"\n")?;
+        Ok(())
+    }
+}
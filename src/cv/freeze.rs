@@ -0,0 +1,75 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::camera::Frame;
+
+/// Pause and single-step state shared between the UI and the CV worker.
+/// While paused the worker keeps re-running the frozen frame.
+#[derive(Debug, Default)]
+pub struct FreezeControl {
+    paused: AtomicBool,
+    step: AtomicBool,
+    /// Last frame taken from the camera; frozen while paused
+    current: Mutex<Option<Frame>>,
+}
+
+impl FreezeControl {
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.step.store(false, Ordering::SeqCst);
+        self.paused.store(paused, Ordering::SeqCst);
+    }
+
+    /// Replace the frozen frame with the next camera frame
+    pub fn step(&self) {
+        self.step.store(true, Ordering::SeqCst);
+    }
+
+    /// The frozen frame, or the latest one taken while running
+    pub fn current(&self) -> Option<Frame> {
+        self.current.lock().unwrap().clone()
+    }
+
+    /// Whether `next_frame` would use a new camera frame. While frozen the
+    /// frame is left in place, so a fast replay waits instead of running on.
+    pub fn wants_frame(&self) -> bool {
+        !self.is_paused() || self.step.load(Ordering::SeqCst) || self.current.lock().unwrap().is_none()
+    }
+
+    /// Picks the frame to run given the newest camera frame, if any. The flag
+    /// is true when the frame was already run before.
+    pub fn next_frame(&self, fresh: Option<Frame>) -> Option<(Frame, bool)> {
+        let mut current = self.current.lock().unwrap();
+
+        let advance = !self.is_paused()
+            || current.is_none()
+            || (fresh.is_some() && self.step.swap(false, Ordering::SeqCst));
+
+        if advance {
+            let frame = fresh?;
+            *current = Some(frame.clone());
+            Some((frame, false))
+        } else {
+            current.clone().map(|frame| (frame, true))
+        }
+    }
+
+    /// Writes the frozen frame to `dir` as an RGBA PNG and returns its path
+    pub fn save_png(&self, dir: &Path) -> Result<PathBuf, Box<dyn Error>> {
+        let frame = self.current().ok_or("No frame captured yet")?;
+        let (width, height, rgba) = frame;
+
+        std::fs::create_dir_all(dir)?;
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+        let path = dir.join(format!("frame_{timestamp}_{width}x{height}.png"));
+
+        image::save_buffer(&path, &rgba.data, width, height, image::ColorType::Rgba8)?;
+        Ok(path)
+    }
+}
@@ -0,0 +1,317 @@
+use std::error::Error;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use raqote::{DrawOptions, DrawTarget, PathBuilder, SolidSource, Source};
+
+use crate::config::{RecordFormat, RecordingConfig};
+use super::{Inference, tasks::{draw_target_to_rgba, draw_text}};
+
+/// Caption font size at 720p; scaled with the frame height
+const CAPTION_FONT_SIZE: f32 = 16.0;
+
+/// Inferences waiting to be encoded before new ones are dropped
+const QUEUE_FRAMES: usize = 8;
+
+/// Where a finished recording went
+#[derive(Clone, Debug)]
+pub struct RecordingSummary {
+    pub path: PathBuf,
+    pub frames: usize,
+    /// Inferences that arrived while the queue was full
+    pub dropped: usize,
+    /// Why the recording ended before `stop`
+    pub ended: Option<String>,
+}
+
+/// Saves snapshots and records the annotated inference stream
+#[derive(Debug)]
+pub struct Recorder {
+    config: RecordingConfig,
+    /// Set while recording; fed by the CV worker
+    queue: Mutex<Option<SyncSender<(Instant, Inference)>>>,
+    dropped: Arc<AtomicUsize>,
+    handle: Mutex<Option<JoinHandle<Result<RecordingSummary, String>>>>,
+}
+
+impl Recorder {
+    pub fn new(config: RecordingConfig) -> Self {
+        Self {
+            config,
+            queue: Mutex::new(None),
+            dropped: Arc::new(AtomicUsize::new(0)),
+            handle: Mutex::new(None),
+        }
+    }
+
+    /// Queue an inference if recording. Never blocks the caller; when the
+    /// encoder falls behind the inference is dropped and counted.
+    pub fn push(&self, inference: &Inference) {
+        if let Some(queue) = self.queue.lock().unwrap().as_ref()
+            && let Err(TrySendError::Full(_)) = queue.try_send((Instant::now(), inference.clone()))
+        {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.handle.lock().unwrap().is_some()
+    }
+
+    /// The recording ended by itself and waits for `stop` to collect it
+    pub fn has_ended(&self) -> bool {
+        self.handle.lock().unwrap().as_ref().is_some_and(JoinHandle::is_finished)
+    }
+
+    /// Save one annotated frame as a PNG
+    pub fn snapshot(&self, inference: &Inference) -> Result<PathBuf, Box<dyn Error>> {
+        std::fs::create_dir_all(&self.config.dir)?;
+        let path = Path::new(&self.config.dir).join(format!("snapshot_{}.png", timestamp()?));
+
+        let (width, height, _) = inference.source;
+        image::save_buffer(&path, &annotate(inference), width, height, image::ColorType::Rgba8)?;
+        Ok(path)
+    }
+
+    /// Record every pushed inference until `stop`
+    pub fn start(&self) -> Result<PathBuf, Box<dyn Error>> {
+        let mut handle = self.handle.lock().unwrap();
+        if handle.is_some() {
+            return Err("Already recording".into());
+        }
+
+        std::fs::create_dir_all(&self.config.dir)?;
+        let name = format!("recording_{}", timestamp()?);
+        let mut sink = match self.config.format {
+            RecordFormat::Images => {
+                let dir = Path::new(&self.config.dir).join(name);
+                std::fs::create_dir_all(&dir)?;
+                Sink::Images(dir)
+            }
+            RecordFormat::Video => Sink::Video {
+                path: Path::new(&self.config.dir).join(format!("{name}.mp4")),
+                fps: self.config.fps,
+                ffmpeg: self.config.ffmpeg.clone(),
+                encoder: None,
+                written: 0,
+            },
+        };
+        let path = sink.path().to_path_buf();
+
+        let (tx, rx) = mpsc::sync_channel::<(Instant, Inference)>(QUEUE_FRAMES);
+        self.dropped.store(0, Ordering::Relaxed);
+        let dropped = self.dropped.clone();
+
+        *handle = Some(thread::Builder::new().name("recorder".to_string()).spawn(move || {
+            let mut frames = 0;
+            let mut start = None;
+            let mut ended = None;
+
+            // Ends once `stop` drops the sender and the queue is drained
+            for (at, inference) in rx {
+                if let Some(reason) = sink.size_change(&inference) {
+                    ended = Some(reason);
+                    break;
+                }
+                let elapsed = at.duration_since(*start.get_or_insert(at));
+                if let Err(e) = sink.write(&inference, frames, elapsed) {
+                    sink.finish().ok();
+                    return Err(format!("Recording failed after {frames} frames: {e}"));
+                }
+                frames += 1;
+            }
+
+            sink.finish().map_err(|e| e.to_string())?;
+            Ok(RecordingSummary {
+                path: sink.path().to_path_buf(),
+                frames,
+                dropped: dropped.load(Ordering::Relaxed),
+                ended,
+            })
+        })?);
+        *self.queue.lock().unwrap() = Some(tx);
+
+        Ok(path)
+    }
+
+    /// Finish the recording; blocks until queued frames are written
+    pub fn stop(&self) -> Result<RecordingSummary, Box<dyn Error>> {
+        self.queue.lock().unwrap().take();
+
+        let handle = self.handle.lock().unwrap().take().ok_or("Not recording")?;
+        let summary = handle.join().map_err(|_| "Recorder thread panicked")??;
+        Ok(summary)
+    }
+}
+
+enum Sink {
+    Images(PathBuf),
+    /// The encoder starts on the first frame, once the size is known
+    Video {
+        path: PathBuf,
+        fps: u32,
+        ffmpeg: String,
+        encoder: Option<(Child, ChildStdin, (u32, u32))>,
+        /// Video frames so far; inferences are repeated or skipped to keep
+        /// the video in real time
+        written: u64,
+    },
+}
+
+impl Sink {
+    fn path(&self) -> &Path {
+        match self {
+            Sink::Images(dir) => dir,
+            Sink::Video { path, .. } => path,
+        }
+    }
+
+    /// `elapsed` is the inference's arrival since the first one
+    fn write(&mut self, inference: &Inference, index: usize, elapsed: Duration) -> Result<(), Box<dyn Error>> {
+        let (width, height, _) = inference.source;
+        let rgba = annotate(inference);
+
+        match self {
+            Sink::Images(dir) => {
+                let path = dir.join(format!("frame_{index:06}.png"));
+                image::save_buffer(path, &rgba, width, height, image::ColorType::Rgba8)?;
+            }
+            Sink::Video { path, fps, ffmpeg, encoder, written } => {
+                if encoder.is_none() {
+                    let mut child = Command::new(&*ffmpeg)
+                        .args(["-loglevel", "error", "-y", "-f", "rawvideo", "-pix_fmt", "rgba"])
+                        .args(["-s", &format!("{width}x{height}"), "-r", &fps.to_string(), "-i", "-"])
+                        .args(["-c:v", "libx264", "-pix_fmt", "yuv420p"])
+                        .arg(&*path)
+                        .stdin(Stdio::piped())
+                        .spawn()
+                        .map_err(|e| format!("Unable to run {ffmpeg}: {e}"))?;
+                    let stdin = child.stdin.take().ok_or("ffmpeg has no stdin")?;
+                    *encoder = Some((child, stdin, (width, height)));
+                }
+
+                let (_, stdin, _) = encoder.as_mut().unwrap();
+
+                // Repeat or skip so video frame n shows what was current at n / fps
+                let due = (elapsed.as_secs_f64() * *fps as f64).floor() as u64 + 1;
+                for _ in *written..due {
+                    stdin.write_all(&rgba)?;
+                }
+                *written = (*written).max(due);
+            }
+        }
+        Ok(())
+    }
+
+    /// A video can't change size midway; the recording stops with this reason
+    fn size_change(&self, inference: &Inference) -> Option<String> {
+        let (width, height, _) = inference.source;
+        match self {
+            Sink::Video { encoder: Some((_, _, size)), .. } if *size != (width, height) => {
+                Some(format!("frame size changed from {}x{} to {width}x{height}", size.0, size.1))
+            }
+            _ => None,
+        }
+    }
+
+    fn finish(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Sink::Video { encoder, .. } = self
+            && let Some((mut child, stdin, _)) = encoder.take()
+        {
+            // Closing stdin ends the stream
+            drop(stdin);
+            let status = child.wait()?;
+            if !status.success() {
+                return Err(format!("ffmpeg exited with {status}").into());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Camera frame with the overlay on top and the frame's metrics in the
+/// top-left corner
+pub fn annotate(inference: &Inference) -> Vec<u8> {
+    let (width, height, source) = &inference.source;
+    let mut out = source.data.clone();
+
+    let overlay = &inference.frame.2.data;
+    if overlay.len() == out.len() {
+        blend(&mut out, overlay);
+    }
+    blend(&mut out, &caption(*width, *height, &metric_lines(inference)));
+    out
+}
+
+fn metric_lines(inference: &Inference) -> Vec<String> {
+    let t = &inference.time_metrics;
+    let mut lines = vec![format!(
+        "Inference {:?}  Pre {:?}  Post {:?}  Render {:?}",
+        t.inference, t.preprocess, t.postprocess, t.render
+    )];
+
+    if let Some(top_down) = &inference.top_down {
+        lines.push(format!("Detector + {} people", top_down.people.len()));
+    }
+    if let Some(batch) = inference.batch {
+        lines.push(format!("Batch {} ({:?} / frame)", batch.size, batch.per_frame));
+    }
+    if let Some(jitter) = inference.jitter {
+        lines.push(format!("Jitter {:.2}px / {:.2}px", jitter.raw, jitter.smoothed));
+    }
+    lines
+}
+
+/// Lines of text on a dark box, as a premultiplied RGBA layer
+fn caption(width: u32, height: u32, lines: &[String]) -> Vec<u8> {
+    let mut dt = DrawTarget::new(width as i32, height as i32);
+
+    let size = CAPTION_FONT_SIZE * height as f32 / 720.0;
+    let line_height = size * 1.4;
+    let longest = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+
+    let mut pb = PathBuilder::new();
+    pb.rect(0.0, 0.0, longest as f32 * size * 0.55 + size, lines.len() as f32 * line_height + size * 0.5);
+    dt.fill(
+        &pb.finish(),
+        &Source::Solid(SolidSource { r: 0, g: 0, b: 0, a: 160 }),
+        &DrawOptions::new(),
+    );
+
+    for (i, line) in lines.iter().enumerate() {
+        draw_text(
+            &mut dt,
+            line,
+            size * 0.5,
+            (i + 1) as f32 * line_height,
+            size,
+            SolidSource { r: 255, g: 255, b: 255, a: 255 },
+        );
+    }
+
+    draw_target_to_rgba(&dt, width, height)
+}
+
+/// Source-over of a premultiplied RGBA layer onto an opaque frame
+fn blend(frame: &mut [u8], layer: &[u8]) {
+    for (dst, src) in frame.chunks_exact_mut(4).zip(layer.chunks_exact(4)) {
+        let alpha = src[3] as u32;
+        if alpha == 0 {
+            continue;
+        }
+        for c in 0..3 {
+            dst[c] = (src[c] as u32 + dst[c] as u32 * (255 - alpha) / 255).min(255) as u8;
+        }
+        dst[3] = 255;
+    }
+}
+
+fn timestamp() -> Result<u128, Box<dyn Error>> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis())
+}
@@ -1,28 +1,73 @@
 use std::{error::Error, fmt::Debug};
 use ndarray::Array4;
+use ort::value::Outlet;
+use serde::Serialize;
 
+use crate::config::InferenceGenericConfig;
+
+mod classify;
+mod common;
+mod depth;
+mod face;
 mod pose;
 mod object;
+mod raw;
 mod segment;
+mod style;
 
-use pose::Keypoints;
 use object::Detections;
 
-pub use pose::PoseTask;
+pub use classify::{Classification, ClassifyTask};
+pub use common::{draw_target_to_rgba, draw_text, normalize, swap_rb};
+pub use depth::{DepthMap, DepthTask};
+pub use face::{Face, FaceTask};
+pub use object::{BBox, Detection, ObjectTask, OrientedDetection};
+pub use pose::{COCO_KEYPOINT_NAMES, ConfidenceStats, ConfidenceTracker, JitterStats, KeypointConfidence, Person, PoseSmoother, PoseTask};
+pub use raw::{RawTask, TensorSummary};
+
+/// Thresholds and overlay filters that can change while the model runs
+#[derive(Clone, Debug, PartialEq)]
+pub struct TaskSettings {
+    pub confidence_threshold: f32,
+    pub iou_threshold: f32,
+    pub hidden_keypoints: Vec<usize>,
+    pub hidden_classes: Vec<usize>,
+}
+
+impl From<&InferenceGenericConfig> for TaskSettings {
+    fn from(generics: &InferenceGenericConfig) -> Self {
+        Self {
+            confidence_threshold: generics.confidence_threshold,
+            iou_threshold: generics.iou_threshold,
+            hidden_keypoints: generics.hidden_keypoints.clone(),
+            hidden_classes: generics.hidden_classes.clone(),
+        }
+    }
+}
 
 pub trait VisionTask: Send + Sync + Debug {
-    fn preprocess(&self,
+    /// Decoding method, reported alongside benchmark results
+    fn decoding(&self) -> Option<String> {
+        None
+    }
+
+    /// Preprocess `region` of the frame: the whole frame, or e.g. one person crop
+    fn preprocess(
+        &self,
         rgba: &[u8],
         width: u32,
-        height: u32,) -> Array4<f32>;
+        height: u32,
+        region: &BBox,
+    ) -> Array4<f32>;
 
+    /// Decode the model outputs, which can be looked up by name. Also
+    /// returns how many candidates the confidence threshold discarded.
     fn postprocess(
         &self,
         outputs: &ort::session::SessionOutputs,
-        output_name: &str,
         orig_width: u32,
         orig_height: u32,
-    ) -> Result<TaskResult, Box<dyn Error>>;
+    ) -> Result<(TaskResult, usize), Box<dyn Error>>;
 
     fn render(
         &self,
@@ -30,10 +75,25 @@ pub trait VisionTask: Send + Sync + Debug {
         width: u32,
         height: u32,
     ) -> Vec<u8>;
+
+    /// Take new thresholds and filters without reloading the model
+    fn apply_settings(&mut self, _settings: &TaskSettings) {}
+
+    /// Look at the model's outputs once after loading, e.g. to pick the
+    /// decoder for their layout
+    fn inspect_outputs(&mut self, _outputs: &[Outlet]) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
 }
 
+#[derive(Clone, Debug, Serialize)]
 pub enum TaskResult {
-    Pose(Keypoints),
-    Detections(Vec<Detections>),
+    Pose(Vec<Person>),
+    Detections(Detections),
+    OrientedDetections(Vec<OrientedDetection>),
     SegmentationMask(Vec<u8>),
+    Classes(Vec<Classification>),
+    Depth(DepthMap),
+    Faces(Vec<Face>),
+    Raw(Vec<TensorSummary>),
 }
\ No newline at end of file
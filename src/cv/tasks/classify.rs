@@ -0,0 +1,190 @@
+use std::{error::Error, fs, path::Path};
+
+use ndarray::Array4;
+use raqote::{DrawOptions, DrawTarget, PathBuilder, Source};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{ClassifyConfig, InferenceGenericConfig, QuantConfig};
+
+use super::{TaskResult, VisionTask, common::{draw_target_to_rgba, draw_text, extract_f32, output_tensor, sample_region, softmax}, object::BBox, style::{OverlayStyle, translucent}};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Classification {
+    pub class_id: usize,
+    pub label: String,
+    pub score: f32,
+}
+
+#[derive(Debug)]
+pub struct ClassifyTask {
+    config: ClassifyConfig,
+    labels: Vec<String>,
+    inf_width: usize,
+    inf_height: usize,
+    output: Option<String>,
+    output_quant: Option<QuantConfig>,
+    style: OverlayStyle,
+}
+
+impl ClassifyTask {
+    pub fn new(generics: &InferenceGenericConfig, config: &ClassifyConfig) -> Result<Self, Box<dyn Error>> {
+        let labels = match &config.labels_path {
+            Some(path) => load_labels(path)?,
+            None => Vec::new(),
+        };
+
+        Ok(Self {
+            config: config.clone(),
+            labels,
+            inf_width: generics.inf_width,
+            inf_height: generics.inf_height,
+            output: generics.output.clone(),
+            output_quant: generics.output_quant,
+            style: OverlayStyle::new(&generics.overlay),
+        })
+    }
+
+    fn label(&self, class_id: usize) -> String {
+        self.labels
+            .get(class_id)
+            .filter(|l| !l.is_empty())
+            .cloned()
+            .unwrap_or_else(|| format!("class {class_id}"))
+    }
+
+    fn render_classes(&self, classes: &[Classification], width: u32, height: u32) -> Vec<u8> {
+        let mut dt = DrawTarget::new(width as i32, height as i32);
+
+        // Bars sized from the label font
+        let font_size = self.style.font_size(height);
+        let row = font_size * 1.75;
+        let bar_width = font_size * 15.0;
+        let (x, y) = (font_size * 0.75, font_size * 0.75);
+
+        for (i, class) in classes.iter().enumerate() {
+            let top = y + i as f32 * row;
+
+            let mut pb = PathBuilder::new();
+            pb.rect(x, top, bar_width, row - font_size * 0.25);
+            dt.fill(
+                &pb.finish(),
+                &Source::Solid(self.style.label_background()),
+                &DrawOptions::new(),
+            );
+
+            let mut pb = PathBuilder::new();
+            pb.rect(x, top, bar_width * class.score.clamp(0.0, 1.0), row - font_size * 0.25);
+            dt.fill(
+                &pb.finish(),
+                &Source::Solid(translucent(self.style.box_color(class.class_id, None), 200)),
+                &DrawOptions::new(),
+            );
+
+            let label = if self.style.show_confidence() {
+                format!("{} {:.1}%", class.label, class.score * 100.0)
+            } else {
+                class.label.clone()
+            };
+            draw_text(
+                &mut dt,
+                &label,
+                x + font_size * 0.375,
+                top + row - font_size * 0.625,
+                font_size,
+                self.style.text_color(),
+            );
+        }
+
+        draw_target_to_rgba(&dt, width, height)
+    }
+}
+
+/// Labels from a text file (one per line) or JSON: a list of strings or an
+/// `{"0": "label", ...}` map as found in `id2label`
+fn load_labels(path: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let contents = fs::read_to_string(path)?;
+
+    if Path::new(path).extension().is_some_and(|ext| ext == "json") {
+        return match serde_json::from_str::<serde_json::Value>(&contents)? {
+            serde_json::Value::Array(items) => items
+                .into_iter()
+                .map(|v| v.as_str().map(str::to_string).ok_or_else(|| "Label is not a string".into()))
+                .collect(),
+            serde_json::Value::Object(map) => {
+                let mut labels = vec![String::new(); map.len()];
+                for (key, value) in map {
+                    let id: usize = key.parse()?;
+                    let label = value.as_str().ok_or("Label is not a string")?;
+                    if id >= labels.len() {
+                        labels.resize(id + 1, String::new());
+                    }
+                    labels[id] = label.to_string();
+                }
+                Ok(labels)
+            }
+            _ => Err("Labels JSON must be a list or an object".into()),
+        };
+    }
+
+    Ok(contents.lines().map(|l| l.trim().to_string()).collect())
+}
+
+impl VisionTask for ClassifyTask {
+    fn preprocess(
+        &self,
+        rgba: &[u8],
+        width: u32,
+        height: u32,
+        region: &BBox,
+    ) -> Array4<f32> {
+        sample_region(rgba, width, height, region, self.inf_width, self.inf_height)
+    }
+
+    fn postprocess(
+        &self,
+        outputs: &ort::session::SessionOutputs,
+        _orig_w: u32,
+        _orig_h: u32,
+    ) -> Result<(TaskResult, usize), Box<dyn Error>> {
+        let tensor = output_tensor(outputs, self.output.as_deref())?;
+
+        let array = extract_f32(tensor, self.output_quant)?;
+
+        // [1, C] or [C]
+        if array.ndim() > 2 || (array.ndim() == 2 && array.shape()[0] != 1) {
+            return Err("Unsupported output shape".into());
+        }
+
+        let mut scores: Vec<f32> = array.iter().copied().collect();
+        if self.config.softmax {
+            softmax(&mut scores);
+        }
+
+        let mut order: Vec<usize> = (0..scores.len()).collect();
+        order.sort_by(|&a, &b| scores[b].total_cmp(&scores[a]));
+
+        let classes = order
+            .into_iter()
+            .take(self.config.top_k)
+            .map(|class_id| Classification {
+                class_id,
+                label: self.label(class_id),
+                score: scores[class_id],
+            })
+            .collect();
+
+        Ok((TaskResult::Classes(classes), 0))
+    }
+
+    fn render(
+        &self,
+        result: &TaskResult,
+        width: u32,
+        height: u32,
+    ) -> Vec<u8> {
+        match result {
+            TaskResult::Classes(classes) => self.render_classes(classes, width, height),
+            _ => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use std::error::Error;
+
+use font_kit::{family_name::FamilyName, font::Font, properties::Properties, source::SystemSource};
+use ndarray::{Array4, ArrayD, Axis, CowArray, IxDyn, Zip, s};
+use ort::{session::SessionOutputs, tensor::TensorElementType, value::DynValue};
+use raqote::{DrawOptions, DrawTarget, Point, SolidSource, Source};
+
+use crate::config::QuantConfig;
+
+use super::object::BBox;
+
+/// Nearest-neighbour resample of `region` of an RGBA frame into a
+/// `[1, 3, h, w]` tensor scaled to 0..1. Pixels outside the frame are zero.
+pub fn sample_region(
+    rgba: &[u8],
+    width: u32,
+    height: u32,
+    region: &BBox,
+    w: usize,
+    h: usize,
+) -> Array4<f32> {
+    let mut input = Array4::<f32>::zeros((1, 3, h, w));
+    let out = input.as_slice_mut().unwrap();
+
+    let hw = h * w;
+    let scale = 1.0 / 255.0;
+
+    let x_ratio = region.width() / w as f32;
+    let y_ratio = region.height() / h as f32;
+
+    for y in 0..h {
+        let src_y = (region.y1 + y as f32 * y_ratio).floor();
+        if src_y < 0.0 || src_y >= height as f32 {
+            continue;
+        }
+        let src_y = src_y as usize;
+
+        for x in 0..w {
+            let src_x = (region.x1 + x as f32 * x_ratio).floor();
+            if src_x < 0.0 || src_x >= width as f32 {
+                continue;
+            }
+            let src_x = src_x as usize;
+
+            let src_i = (src_y * width as usize + src_x) * 4;
+            let dst_i = y * w + x;
+
+            let r = rgba[src_i];
+            let g = rgba[src_i + 1];
+            let b = rgba[src_i + 2];
+
+            out[dst_i] = r as f32 * scale;
+            out[hw + dst_i] = g as f32 * scale;
+            out[2 * hw + dst_i] = b as f32 * scale;
+        }
+    }
+
+    input
+}
+
+pub fn softmax(logits: &mut [f32]) {
+    let max = logits.iter().copied().fold(f32::MIN, f32::max);
+    let mut sum = 0.0;
+    for v in logits.iter_mut() {
+        *v = (*v - max).exp();
+        sum += *v;
+    }
+    for v in logits.iter_mut() {
+        *v /= sum;
+    }
+}
+
+pub fn sigmoid(x: f32) -> f32 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+/// The output called `name`, or the model's first output
+pub fn output_tensor<'a>(
+    outputs: &'a SessionOutputs,
+    name: Option<&str>,
+) -> Result<&'a DynValue, Box<dyn Error>> {
+    let name = match name {
+        Some(name) => name,
+        None => outputs.keys().next().ok_or("Model has no outputs")?,
+    };
+    outputs
+        .get(name)
+        .ok_or_else(|| format!("Missing output tensor {name}").into())
+}
+
+/// Numeric tensor contents widened to f32, in row-major order
+pub fn numeric_values(value: &DynValue) -> Option<Vec<f32>> {
+    match value.dtype().tensor_type()? {
+        TensorElementType::Float32 => value.try_extract_tensor::<f32>().ok().map(|(_, d)| d.to_vec()),
+        TensorElementType::Float16 => value.try_extract_tensor::<half::f16>().ok().map(|(_, d)| d.iter().map(|v| v.to_f32()).collect()),
+        TensorElementType::Float64 => value.try_extract_tensor::<f64>().ok().map(|(_, d)| d.iter().map(|&v| v as f32).collect()),
+        TensorElementType::Int64 => value.try_extract_tensor::<i64>().ok().map(|(_, d)| d.iter().map(|&v| v as f32).collect()),
+        TensorElementType::Int32 => value.try_extract_tensor::<i32>().ok().map(|(_, d)| d.iter().map(|&v| v as f32).collect()),
+        TensorElementType::Int8 => value.try_extract_tensor::<i8>().ok().map(|(_, d)| d.iter().map(|&v| v as f32).collect()),
+        TensorElementType::Uint8 => value.try_extract_tensor::<u8>().ok().map(|(_, d)| d.iter().map(|&v| v as f32).collect()),
+        TensorElementType::Bool => value.try_extract_tensor::<bool>().ok().map(|(_, d)| d.iter().map(|&v| v as u8 as f32).collect()),
+        _ => None,
+    }
+}
+
+/// Output as an f32 array for decoding. f32 outputs are borrowed; f16 and
+/// integer outputs are converted, u8/i8 ones dequantized with `quant`.
+pub fn extract_f32(value: &DynValue, quant: Option<QuantConfig>) -> Result<CowArray<'_, f32, IxDyn>, Box<dyn Error>> {
+    if value.dtype().tensor_type() == Some(TensorElementType::Float32) {
+        return Ok(value.try_extract_array::<f32>()?.into());
+    }
+
+    let shape: Vec<usize> = value
+        .dtype()
+        .tensor_shape()
+        .ok_or("Output is not a tensor")?
+        .iter()
+        .map(|&d| d as usize)
+        .collect();
+    let mut values = numeric_values(value).ok_or_else(|| format!("Unsupported output type {}", value.dtype()))?;
+
+    if let Some(ty @ (TensorElementType::Uint8 | TensorElementType::Int8)) = value.dtype().tensor_type() {
+        // Raw codes would fail every 0..1 threshold
+        let q = quant.ok_or_else(|| format!("{ty} output needs output_quant"))?;
+        values.iter_mut().for_each(|v| *v = (*v - q.zero_point as f32) * q.scale);
+    }
+
+    Ok(ArrayD::from_shape_vec(shape, values)?.into())
+}
+
+/// Unpack raqote's premultiplied ARGB pixels into an RGBA buffer
+pub fn draw_target_to_rgba(dt: &DrawTarget, width: u32, height: u32) -> Vec<u8> {
+    let data = dt.get_data();
+    let mut out = Vec::with_capacity((width * height * 4) as usize);
+
+    for px in data {
+        out.push((px >> 16) as u8); // R
+        out.push((px >> 8) as u8);  // G
+        out.push(*px as u8);        // B
+        out.push((px >> 24) as u8); // A
+    }
+    out
+}
+
+/// Per-channel `(x - mean) / std` on a `[1, 3, h, w]` tensor
+pub fn normalize(input: &mut Array4<f32>, mean: [f32; 3], std: [f32; 3]) {
+    if mean == [0.0; 3] && std == [1.0; 3] {
+        return;
+    }
+
+    for (c, mut channel) in input.axis_iter_mut(Axis(1)).enumerate() {
+        channel.mapv_inplace(|v| (v - mean[c]) / std[c]);
+    }
+}
+
+/// Swap the R and B planes of a `[n, 3, h, w]` tensor for BGR models
+pub fn swap_rb(input: &mut Array4<f32>) {
+    let (mut r, mut b) = input.multi_slice_mut((s![.., 0, .., ..], s![.., 2, .., ..]));
+    Zip::from(&mut r).and(&mut b).for_each(std::mem::swap);
+}
+
+thread_local! {
+    // Fonts aren't Send, so each worker thread loads its own copy
+    static FONT: Option<Font> = SystemSource::new()
+        .select_best_match(&[FamilyName::SansSerif], &Properties::new())
+        .ok()
+        .and_then(|handle| handle.load().ok());
+}
+
+/// Draw `text` with its baseline starting at (x, y). Draws nothing if no
+/// system font could be loaded.
+pub fn draw_text(dt: &mut DrawTarget, text: &str, x: f32, y: f32, size: f32, color: SolidSource) {
+    FONT.with(|font| {
+        if let Some(font) = font {
+            dt.draw_text(font, size, text, Point::new(x, y), &Source::Solid(color), &DrawOptions::new());
+        }
+    });
+}
@@ -0,0 +1,136 @@
+mod colormap;
+
+use std::error::Error;
+
+use ndarray::{Array4, Axis};
+use serde::Serialize;
+
+use crate::config::{DepthConfig, InferenceGenericConfig, QuantConfig};
+
+use super::{TaskResult, VisionTask, common::{extract_f32, output_tensor, sample_region}, object::BBox};
+
+/// Model-resolution depth, normalized to 0..1
+#[derive(Clone, Debug, Serialize)]
+pub struct DepthMap {
+    pub width: usize,
+    pub height: usize,
+    /// Raw output range mapped to 0..1
+    pub min: f32,
+    pub max: f32,
+    #[serde(skip)]
+    pub values: Vec<f32>,
+}
+
+impl DepthMap {
+    /// Bilinear sample at fractional map coordinates
+    fn sample(&self, x: f32, y: f32) -> f32 {
+        let x = x.clamp(0.0, (self.width - 1) as f32);
+        let y = y.clamp(0.0, (self.height - 1) as f32);
+
+        let (x0, y0) = (x as usize, y as usize);
+        let x1 = (x0 + 1).min(self.width - 1);
+        let y1 = (y0 + 1).min(self.height - 1);
+        let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+
+        let at = |x: usize, y: usize| self.values[y * self.width + x];
+        let top = at(x0, y0) * (1.0 - fx) + at(x1, y0) * fx;
+        let bottom = at(x0, y1) * (1.0 - fx) + at(x1, y1) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+}
+
+#[derive(Debug)]
+pub struct DepthTask {
+    config: DepthConfig,
+    lut: [[u8; 3]; 256],
+    inf_width: usize,
+    inf_height: usize,
+    output: Option<String>,
+    output_quant: Option<QuantConfig>,
+}
+
+impl DepthTask {
+    pub fn new(generics: &InferenceGenericConfig, config: &DepthConfig) -> Self {
+        Self {
+            config: config.clone(),
+            lut: colormap::lut(config.colormap),
+            inf_width: generics.inf_width,
+            inf_height: generics.inf_height,
+            output: generics.output.clone(),
+            output_quant: generics.output_quant,
+        }
+    }
+
+    fn render_depth(&self, depth: &DepthMap, width: u32, height: u32) -> Vec<u8> {
+        let mut out = Vec::with_capacity((width * height * 4) as usize);
+        let alpha = (self.config.opacity.clamp(0.0, 1.0) * 255.0) as u8;
+
+        let x_ratio = depth.width as f32 / width as f32;
+        let y_ratio = depth.height as f32 / height as f32;
+
+        for y in 0..height {
+            let src_y = (y as f32 + 0.5) * y_ratio - 0.5;
+            for x in 0..width {
+                let src_x = (x as f32 + 0.5) * x_ratio - 0.5;
+                let v = depth.sample(src_x, src_y);
+
+                let [r, g, b] = self.lut[(v.clamp(0.0, 1.0) * 255.0) as usize];
+                out.extend_from_slice(&[r, g, b, alpha]);
+            }
+        }
+        out
+    }
+}
+
+impl VisionTask for DepthTask {
+    fn preprocess(
+        &self,
+        rgba: &[u8],
+        width: u32,
+        height: u32,
+        region: &BBox,
+    ) -> Array4<f32> {
+        sample_region(rgba, width, height, region, self.inf_width, self.inf_height)
+    }
+
+    fn postprocess(
+        &self,
+        outputs: &ort::session::SessionOutputs,
+        _orig_w: u32,
+        _orig_h: u32,
+    ) -> Result<(TaskResult, usize), Box<dyn Error>> {
+        let tensor = output_tensor(outputs, self.output.as_deref())?;
+
+        let array = extract_f32(tensor, self.output_quant)?;
+
+        // [1, 1, H, W] or [1, H, W]
+        let map = match array.ndim() {
+            4 => array.index_axis(Axis(0), 0).index_axis_move(Axis(0), 0),
+            3 => array.index_axis(Axis(0), 0),
+            _ => return Err("Unsupported output shape".into()),
+        };
+        let (height, width) = (map.shape()[0], map.shape()[1]);
+
+        let (min, max) = match self.config.range {
+            Some([min, max]) => (min, max),
+            None => map.iter().fold((f32::MAX, f32::MIN), |(lo, hi), &v| (lo.min(v), hi.max(v))),
+        };
+        let span = (max - min).max(f32::EPSILON);
+
+        let values = map.iter().map(|&v| ((v - min) / span).clamp(0.0, 1.0)).collect();
+
+        Ok((TaskResult::Depth(DepthMap { width, height, min, max, values }), 0))
+    }
+
+    fn render(
+        &self,
+        result: &TaskResult,
+        width: u32,
+        height: u32,
+    ) -> Vec<u8> {
+        match result {
+            TaskResult::Depth(depth) => self.render_depth(depth, width, height),
+            _ => unreachable!(),
+        }
+    }
+}
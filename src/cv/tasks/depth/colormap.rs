@@ -0,0 +1,38 @@
+use crate::config::Colormap;
+
+/// 256-entry RGB lookup table for `colormap`
+pub fn lut(colormap: Colormap) -> [[u8; 3]; 256] {
+    let mut table = [[0u8; 3]; 256];
+    for (i, entry) in table.iter_mut().enumerate() {
+        let t = i as f32 / 255.0;
+        let [r, g, b] = match colormap {
+            Colormap::Turbo => turbo(t),
+            Colormap::Viridis => viridis(t),
+            Colormap::Grayscale => [t, t, t],
+        };
+        *entry = [r, g, b].map(|c| (c.clamp(0.0, 1.0) * 255.0) as u8);
+    }
+    table
+}
+
+// Polynomial fit of Google's Turbo colormap
+fn turbo(t: f32) -> [f32; 3] {
+    const R: [f32; 6] = [0.135_721_38, 4.615_392_6, -42.660_324, 132.131_08, -152.942_4, 59.286_38];
+    const G: [f32; 6] = [0.091_402_61, 2.194_188_4, 4.842_966_6, -14.185_033, 4.277_298_6, 2.829_566];
+    const B: [f32; 6] = [0.106_673_3, 12.641_946, -60.582_05, 110.362_77, -89.903_11, 27.348_25];
+
+    [poly(&R, t), poly(&G, t), poly(&B, t)]
+}
+
+// Polynomial fit of matplotlib's viridis
+fn viridis(t: f32) -> [f32; 3] {
+    const R: [f32; 7] = [0.277_727_33, 0.105_093_04, -0.330_861_83, -4.634_230_6, 6.228_27, 4.776_385, -5.435_456];
+    const G: [f32; 7] = [0.005_407_344_5, 1.404_613_5, 0.214_847_56, -5.799_101, 14.179_933, -13.745_145, 4.645_852_6];
+    const B: [f32; 7] = [0.334_099_8, 1.384_590_2, 0.095_095_16, -19.332_441, 56.690_55, -65.353_03, 26.312_435];
+
+    [poly(&R, t), poly(&G, t), poly(&B, t)]
+}
+
+fn poly(coeffs: &[f32], t: f32) -> f32 {
+    coeffs.iter().rev().fold(0.0, |acc, c| acc * t + c)
+}
@@ -0,0 +1,372 @@
+use std::error::Error;
+
+use ndarray::Array4;
+use raqote::{DrawOptions, DrawTarget, PathBuilder, Source, StrokeStyle};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{FaceConfig, FaceFormat, InferenceGenericConfig, QuantConfig};
+
+use super::{TaskResult, TaskSettings, VisionTask, common::{draw_target_to_rgba, draw_text, extract_f32, sample_region}, object::{BBox, Detection, nms_indices}, style::{OverlayStyle, solid}};
+
+/// Eyes, nose tip and mouth corners; BlazeFace adds two ear points
+const LANDMARK_COLORS: [(u8, u8, u8); 6] = [
+    (255, 0, 0),
+    (0, 0, 255),
+    (0, 255, 0),
+    (255, 0, 255),
+    (255, 255, 0),
+    (0, 255, 255),
+];
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Face {
+    pub bbox: BBox,
+    pub score: f32,
+    pub track_id: Option<u32>,
+    pub landmarks: Vec<(f32, f32)>,
+}
+
+impl Face {
+    pub fn detection(&self) -> Detection {
+        Detection {
+            bbox: self.bbox,
+            score: self.score,
+            class_id: 0,
+            track_id: self.track_id,
+        }
+    }
+}
+
+/// Feature map cell an anchor sits on
+#[derive(Clone, Copy, Debug)]
+struct Anchor {
+    x: f32,
+    y: f32,
+    stride: f32,
+}
+
+#[derive(Debug)]
+pub struct FaceTask {
+    format: FaceFormat,
+    strides: Vec<usize>,
+    anchors_per_stride: usize,
+    /// One group per output set: a stride for SCRFD/YuNet, all anchors for BlazeFace
+    anchors: Vec<Vec<Anchor>>,
+    inf_width: usize,
+    inf_height: usize,
+    output_quant: Option<QuantConfig>,
+    confidence_threshold: f32,
+    iou_threshold: f32,
+    style: OverlayStyle,
+}
+
+impl FaceTask {
+    pub fn new(generics: &InferenceGenericConfig, config: &FaceConfig) -> Self {
+        let (default_strides, default_anchors) = match config.format {
+            FaceFormat::Scrfd => (vec![8, 16, 32], 2),
+            FaceFormat::Yunet => (vec![8, 16, 32], 1),
+            FaceFormat::BlazeFace => (vec![8, 16, 16, 16], 2),
+        };
+        let strides = config.strides.clone().unwrap_or(default_strides);
+        let anchors_per_stride = config.anchors_per_stride.unwrap_or(default_anchors);
+
+        let grid = |stride: usize, per_cell: usize| {
+            let (fw, fh) = (generics.inf_width.div_ceil(stride), generics.inf_height.div_ceil(stride));
+            let mut anchors = Vec::with_capacity(fw * fh * per_cell);
+            for y in 0..fh {
+                for x in 0..fw {
+                    let anchor = Anchor { x: x as f32, y: y as f32, stride: stride as f32 };
+                    anchors.extend(std::iter::repeat_n(anchor, per_cell));
+                }
+            }
+            anchors
+        };
+
+        let anchors = match config.format {
+            FaceFormat::Scrfd | FaceFormat::Yunet => strides
+                .iter()
+                .map(|&s| grid(s, anchors_per_stride))
+                .collect(),
+            // SSD anchors: consecutive layers with the same stride share a grid,
+            // with all their anchors interleaved per cell
+            FaceFormat::BlazeFace => vec![strides
+                .chunk_by(|a, b| a == b)
+                .flat_map(|run| grid(run[0], anchors_per_stride * run.len()))
+                .collect()],
+        };
+
+        Self {
+            format: config.format,
+            strides,
+            anchors_per_stride,
+            anchors,
+            inf_width: generics.inf_width,
+            inf_height: generics.inf_height,
+            output_quant: generics.output_quant,
+            confidence_threshold: generics.confidence_threshold,
+            iou_threshold: generics.iou_threshold,
+            style: OverlayStyle::new(&generics.overlay),
+        }
+    }
+
+    /// SCRFD: sigmoid scores, `l, t, r, b` distances and landmark offsets from
+    /// the anchor point, all in stride units
+    fn decode_scrfd(&self, outputs: &ort::session::SessionOutputs) -> Result<(Vec<Face>, usize), Box<dyn Error>> {
+        let mut faces = Vec::new();
+        let mut discarded = 0;
+
+        for anchors in &self.anchors {
+            let (scores, _) = self.output_rows(outputs, anchors.len(), "score", |w| w == 1)?;
+            let (boxes, _) = self.output_rows(outputs, anchors.len(), "bbox", |w| w == 4)?;
+            let (kps, kps_width) = self.output_rows(outputs, anchors.len(), "kps", is_landmarks)?;
+
+            for (a, anchor) in anchors.iter().enumerate() {
+                let score = scores[a];
+                if score < self.confidence_threshold {
+                    discarded += 1;
+                    continue;
+                }
+
+                let (cx, cy, s) = (anchor.x * anchor.stride, anchor.y * anchor.stride, anchor.stride);
+                let d = &boxes[a * 4..a * 4 + 4];
+                let k = &kps[a * kps_width..(a + 1) * kps_width];
+
+                faces.push(Face {
+                    bbox: BBox { x1: cx - d[0] * s, y1: cy - d[1] * s, x2: cx + d[2] * s, y2: cy + d[3] * s },
+                    score,
+                    track_id: None,
+                    landmarks: k.chunks_exact(2).map(|p| (cx + p[0] * s, cy + p[1] * s)).collect(),
+                });
+            }
+        }
+        Ok((faces, discarded))
+    }
+
+    /// YuNet: score is `sqrt(cls * obj)`, box center and landmarks are cell
+    /// offsets, box size is log-encoded
+    fn decode_yunet(&self, outputs: &ort::session::SessionOutputs) -> Result<(Vec<Face>, usize), Box<dyn Error>> {
+        let mut faces = Vec::new();
+        let mut discarded = 0;
+
+        for anchors in &self.anchors {
+            let (cls, _) = self.output_rows(outputs, anchors.len(), "cls", |w| w == 1)?;
+            let (obj, _) = self.output_rows(outputs, anchors.len(), "obj", |w| w == 1)?;
+            let (boxes, _) = self.output_rows(outputs, anchors.len(), "bbox", |w| w == 4)?;
+            let (kps, kps_width) = self.output_rows(outputs, anchors.len(), "kps", is_landmarks)?;
+
+            for (a, anchor) in anchors.iter().enumerate() {
+                let score = (cls[a].clamp(0.0, 1.0) * obj[a].clamp(0.0, 1.0)).sqrt();
+                if score < self.confidence_threshold {
+                    discarded += 1;
+                    continue;
+                }
+
+                let s = anchor.stride;
+                let b = &boxes[a * 4..a * 4 + 4];
+                let k = &kps[a * kps_width..(a + 1) * kps_width];
+
+                faces.push(Face {
+                    bbox: BBox::from_cxcywh(
+                        (anchor.x + b[0]) * s,
+                        (anchor.y + b[1]) * s,
+                        b[2].exp() * s,
+                        b[3].exp() * s,
+                    ),
+                    score,
+                    track_id: None,
+                    landmarks: k
+                        .chunks_exact(2)
+                        .map(|p| ((anchor.x + p[0]) * s, (anchor.y + p[1]) * s))
+                        .collect(),
+                });
+            }
+        }
+        Ok((faces, discarded))
+    }
+
+    /// BlazeFace: `[1, N, 4 + 2K]` regressors in input pixels relative to the
+    /// anchor center and `[1, N, 1]` logits
+    fn decode_blazeface(&self, outputs: &ort::session::SessionOutputs) -> Result<(Vec<Face>, usize), Box<dyn Error>> {
+        let anchors = &self.anchors[0];
+        let (regressors, reg_width) = self.output_rows(outputs, anchors.len(), "regressors", |w| w >= 4 && w.is_multiple_of(2))?;
+        let (logits, _) = self.output_rows(outputs, anchors.len(), "classificators", |w| w == 1)?;
+
+        let mut faces = Vec::new();
+        let mut discarded = 0;
+        for (a, anchor) in anchors.iter().enumerate() {
+            let score = 1.0 / (1.0 + (-logits[a].clamp(-100.0, 100.0)).exp());
+            if score < self.confidence_threshold {
+                discarded += 1;
+                continue;
+            }
+
+            let ax = (anchor.x + 0.5) * anchor.stride;
+            let ay = (anchor.y + 0.5) * anchor.stride;
+            let r = &regressors[a * reg_width..(a + 1) * reg_width];
+
+            faces.push(Face {
+                bbox: BBox::from_cxcywh(ax + r[0], ay + r[1], r[2], r[3]),
+                score,
+                track_id: None,
+                landmarks: r[4..].chunks_exact(2).map(|p| (ax + p[0], ay + p[1])).collect(),
+            });
+        }
+        Ok((faces, discarded))
+    }
+
+    /// The output with one row per anchor whose row width passes `width`,
+    /// flattened. Names containing `hint` break ties, e.g. YuNet's `cls_8` and
+    /// `obj_8`. Returns the values and the row width.
+    fn output_rows(
+        &self,
+        outputs: &ort::session::SessionOutputs,
+        anchors: usize,
+        hint: &str,
+        width: impl Fn(usize) -> bool,
+    ) -> Result<(Vec<f32>, usize), Box<dyn Error>> {
+        let mut matches: Vec<_> = outputs
+            .iter()
+            .filter_map(|(name, value)| {
+                let shape = value.dtype().tensor_shape()?;
+                let (&last, rows) = shape.split_last()?;
+                let rows: i64 = rows.iter().product();
+                (rows as usize == anchors && width(last as usize)).then_some((name, value, last as usize))
+            })
+            .collect();
+
+        if matches.len() > 1 {
+            matches.retain(|(name, ..)| name.contains(hint));
+        }
+
+        match matches.as_slice() {
+            [(_, value, row_width)] => Ok((extract_f32(value, self.output_quant)?.iter().copied().collect(), *row_width)),
+            [] => Err(format!("No {hint} output with {anchors} rows").into()),
+            _ => Err(format!("Several outputs could be the {hint} for {anchors} anchors").into()),
+        }
+    }
+
+    fn render_faces(&self, faces: &[Face], width: u32, height: u32) -> Vec<u8> {
+        let mut dt = DrawTarget::new(width as i32, height as i32);
+        let line_width = self.style.line_width(height);
+        let radius = self.style.point_radius(height);
+
+        for face in faces {
+            let color = self.style.box_color(0, face.track_id);
+
+            let mut pb = PathBuilder::new();
+            pb.rect(face.bbox.x1, face.bbox.y1, face.bbox.width(), face.bbox.height());
+
+            dt.stroke(
+                &pb.finish(),
+                &Source::Solid(solid(color)),
+                &StrokeStyle {
+                    width: line_width,
+                    ..Default::default()
+                },
+                &DrawOptions::new(),
+            );
+
+            if self.style.show_confidence() {
+                draw_text(
+                    &mut dt,
+                    &format!("{:.0}%", face.score * 100.0),
+                    face.bbox.x1,
+                    face.bbox.y1 - line_width * 2.0,
+                    self.style.font_size(height),
+                    solid(color),
+                );
+            }
+
+            for (i, &(x, y)) in face.landmarks.iter().enumerate() {
+                let color = self.style.keypoint_color(i, LANDMARK_COLORS[i % LANDMARK_COLORS.len()]);
+
+                let mut pb = PathBuilder::new();
+                pb.arc(x, y, radius * 0.75, 0.0, std::f32::consts::TAU);
+
+                dt.fill(
+                    &pb.finish(),
+                    &Source::Solid(solid(color)),
+                    &DrawOptions::new(),
+                );
+            }
+        }
+
+        draw_target_to_rgba(&dt, width, height)
+    }
+}
+
+/// Landmark rows are `x, y` pairs; 4 wide would be a box
+fn is_landmarks(width: usize) -> bool {
+    width > 4 && width.is_multiple_of(2)
+}
+
+impl VisionTask for FaceTask {
+    fn decoding(&self) -> Option<String> {
+        Some(format!("{:?} strides {:?} x{}", self.format, self.strides, self.anchors_per_stride))
+    }
+
+    fn preprocess(
+        &self,
+        rgba: &[u8],
+        width: u32,
+        height: u32,
+        region: &BBox,
+    ) -> Array4<f32> {
+        sample_region(rgba, width, height, region, self.inf_width, self.inf_height)
+    }
+
+    fn postprocess(
+        &self,
+        outputs: &ort::session::SessionOutputs,
+        orig_w: u32,
+        orig_h: u32,
+    ) -> Result<(TaskResult, usize), Box<dyn Error>> {
+        let (faces, discarded) = match self.format {
+            FaceFormat::Scrfd => self.decode_scrfd(outputs)?,
+            FaceFormat::Yunet => self.decode_yunet(outputs)?,
+            FaceFormat::BlazeFace => self.decode_blazeface(outputs)?,
+        };
+
+        let detections: Vec<Detection> = faces.iter().map(Face::detection).collect();
+
+        let scale_x = orig_w as f32 / self.inf_width as f32;
+        let scale_y = orig_h as f32 / self.inf_height as f32;
+
+        let faces = nms_indices(&detections, self.iou_threshold)
+            .into_iter()
+            .map(|i| {
+                let mut face = faces[i].clone();
+                face.bbox = BBox {
+                    x1: face.bbox.x1 * scale_x,
+                    y1: face.bbox.y1 * scale_y,
+                    x2: face.bbox.x2 * scale_x,
+                    y2: face.bbox.y2 * scale_y,
+                };
+                for (x, y) in face.landmarks.iter_mut() {
+                    *x *= scale_x;
+                    *y *= scale_y;
+                }
+                face
+            })
+            .collect();
+
+        Ok((TaskResult::Faces(faces), discarded))
+    }
+
+    fn render(
+        &self,
+        result: &TaskResult,
+        width: u32,
+        height: u32,
+    ) -> Vec<u8> {
+        match result {
+            TaskResult::Faces(faces) => self.render_faces(faces, width, height),
+            _ => unreachable!(),
+        }
+    }
+
+    fn apply_settings(&mut self, settings: &TaskSettings) {
+        self.confidence_threshold = settings.confidence_threshold;
+        self.iou_threshold = settings.iou_threshold;
+    }
+}
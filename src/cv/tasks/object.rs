@@ -1 +1,604 @@
-pub type Detections = Option<u32>;
\ No newline at end of file
+mod rotated;
+
+use std::error::Error;
+
+use ndarray::{Array3, Array4, ArrayView2, Axis, Ix2, s};
+use raqote::{DrawOptions, DrawTarget, PathBuilder, Source, StrokeStyle};
+use ort::value::Outlet;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{BoxFormat, DetrScoring, InferenceGenericConfig, InputSource, ObjectConfig, QuantConfig};
+
+pub use rotated::{OrientedDetection, RotatedBox};
+
+use super::{TaskResult, TaskSettings, VisionTask, common::{draw_target_to_rgba, draw_text, extract_f32, numeric_values, output_tensor, sample_region, sigmoid, softmax}, style::{OverlayStyle, solid}};
+
+/// Axis-aligned box in frame pixels
+#[derive(Clone, Debug, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct BBox {
+    pub x1: f32,
+    pub y1: f32,
+    pub x2: f32,
+    pub y2: f32,
+}
+
+impl BBox {
+    /// The whole frame
+    pub fn frame(width: u32, height: u32) -> Self {
+        Self { x1: 0.0, y1: 0.0, x2: width as f32, y2: height as f32 }
+    }
+
+    pub fn from_cxcywh(cx: f32, cy: f32, w: f32, h: f32) -> Self {
+        Self {
+            x1: cx - w / 2.0,
+            y1: cy - h / 2.0,
+            x2: cx + w / 2.0,
+            y2: cy + h / 2.0,
+        }
+    }
+
+    pub fn width(&self) -> f32 {
+        (self.x2 - self.x1).max(0.0)
+    }
+
+    pub fn height(&self) -> f32 {
+        (self.y2 - self.y1).max(0.0)
+    }
+
+    pub fn area(&self) -> f32 {
+        self.width() * self.height()
+    }
+
+    pub fn center(&self) -> (f32, f32) {
+        ((self.x1 + self.x2) / 2.0, (self.y1 + self.y2) / 2.0)
+    }
+
+    /// Grow by `padding` around the center, then widen or heighten to `aspect` (w / h)
+    pub fn padded_to_aspect(&self, padding: f32, aspect: f32) -> Self {
+        let (cx, cy) = self.center();
+        let mut w = self.width() * padding;
+        let mut h = self.height() * padding;
+
+        if w / h > aspect {
+            h = w / aspect;
+        } else {
+            w = h * aspect;
+        }
+        Self::from_cxcywh(cx, cy, w, h)
+    }
+
+    pub fn iou(&self, other: &BBox) -> f32 {
+        let inter = BBox {
+            x1: self.x1.max(other.x1),
+            y1: self.y1.max(other.y1),
+            x2: self.x2.min(other.x2),
+            y2: self.y2.min(other.y2),
+        }
+        .area();
+
+        let union = self.area() + other.area() - inter;
+        if union <= 0.0 { 0.0 } else { inter / union }
+    }
+}
+
+#[derive(Clone, Debug, Copy, Serialize, Deserialize)]
+pub struct Detection {
+    pub bbox: BBox,
+    pub score: f32,
+    pub class_id: usize,
+    pub track_id: Option<u32>,
+}
+
+pub type Detections = Vec<Detection>;
+
+/// Greedy per-class non-maximum suppression. Returns the indices of the
+/// kept detections, highest score first.
+pub fn nms_indices(detections: &[Detection], iou_threshold: f32) -> Vec<usize> {
+    greedy_nms(detections.len(), |i| detections[i].score, |a, b| {
+        let (a, b) = (&detections[a], &detections[b]);
+        a.class_id == b.class_id && a.bbox.iou(&b.bbox) > iou_threshold
+    })
+}
+
+/// `nms_indices` on rotated IoU
+pub fn nms_rotated_indices(detections: &[OrientedDetection], iou_threshold: f32) -> Vec<usize> {
+    greedy_nms(detections.len(), |i| detections[i].score, |a, b| {
+        let (a, b) = (&detections[a], &detections[b]);
+        a.class_id == b.class_id && a.rbox.iou(&b.rbox) > iou_threshold
+    })
+}
+
+fn greedy_nms(
+    len: usize,
+    score: impl Fn(usize) -> f32,
+    overlaps: impl Fn(usize, usize) -> bool,
+) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..len).collect();
+    order.sort_by(|&a, &b| score(b).total_cmp(&score(a)));
+
+    let mut kept: Vec<usize> = Vec::with_capacity(order.len());
+    for i in order {
+        if !kept.iter().any(|&k| overlaps(k, i)) {
+            kept.push(i);
+        }
+    }
+    kept
+}
+
+pub fn nms(detections: Detections, iou_threshold: f32) -> Detections {
+    nms_indices(&detections, iou_threshold)
+        .into_iter()
+        .map(|i| detections[i])
+        .collect()
+}
+
+/// Where an `ExportedNms` model puts its detections
+#[derive(Debug, Clone)]
+enum NmsLayout {
+    /// Without a count output (e.g. RT-DETR's labels/boxes/scores) every
+    /// row is a candidate, filtered by score
+    Split { num: Option<String>, boxes: String, scores: String, classes: String },
+    /// `[N, 7]`, with the batch index first
+    BatchRows,
+    /// `[1, K, 6]`
+    Rows,
+}
+
+impl NmsLayout {
+    /// Read off the output names and shapes once after loading
+    fn detect(outputs: &[Outlet], output: Option<&str>) -> Result<Self, Box<dyn Error>> {
+        let find = |patterns: &[&str]| {
+            outputs.iter().map(Outlet::name).find(|name| {
+                let name = name.to_lowercase();
+                patterns.iter().any(|p| name.contains(p))
+            })
+        };
+        if outputs.len() >= 3
+            && let (Some(boxes), Some(scores), Some(classes)) =
+                (find(&["box"]), find(&["score"]), find(&["class", "label"]))
+        {
+            return Ok(Self::Split {
+                num: find(&["num"]).map(str::to_string),
+                boxes: boxes.to_string(),
+                scores: scores.to_string(),
+                classes: classes.to_string(),
+            });
+        }
+
+        let outlet = match output {
+            Some(name) => outputs.iter().find(|o| o.name() == name),
+            None => outputs.first(),
+        };
+        let shape = outlet.and_then(|o| o.dtype().tensor_shape()).map(|s| s.to_vec()).unwrap_or_default();
+        match shape.as_slice() {
+            [_, 7] => Ok(Self::BatchRows),
+            [_, _, 6] => Ok(Self::Rows),
+            _ => Err(format!(
+                "ExportedNms needs boxes/scores/classes outputs, or [N, 7] or [1, K, 6] rows; got {shape:?}"
+            ).into()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ObjectTask {
+    config: ObjectConfig,
+    /// Set at load for `ExportedNms`, or a `Yolo` model whose outputs turn
+    /// out to have NMS in the graph
+    nms_layout: Option<NmsLayout>,
+    /// An input carries the frame size, so exported boxes are in frame pixels
+    frame_pixels: bool,
+    inf_width: usize,
+    inf_height: usize,
+    output: Option<String>,
+    output_quant: Option<QuantConfig>,
+    confidence_threshold: f32,
+    iou_threshold: f32,
+    hidden_classes: Vec<usize>,
+    style: OverlayStyle,
+}
+
+impl ObjectTask {
+    pub fn new(generics: &InferenceGenericConfig, config: &ObjectConfig) -> Self {
+        Self {
+            config: config.clone(),
+            nms_layout: None,
+            frame_pixels: generics.inputs.iter().any(|input| {
+                matches!(input.source, InputSource::ImageSize { .. } | InputSource::ScaleFactor { .. })
+            }),
+            inf_width: generics.inf_width,
+            inf_height: generics.inf_height,
+            output: generics.output.clone(),
+            output_quant: generics.output_quant,
+            confidence_threshold: generics.confidence_threshold,
+            iou_threshold: generics.iou_threshold,
+            hidden_classes: generics.hidden_classes.clone(),
+            style: OverlayStyle::new(&generics.overlay),
+        }
+    }
+
+    /// YOLOv8-style `[1, 4 + C, N]`: cx, cy, w, h followed by per-class scores
+    fn decode_yolo(&self, preds: &Array3<f32>, orig_w: u32, orig_h: u32) -> (Detections, usize) {
+        let preds = preds.index_axis(Axis(0), 0);
+        let preds = preds.permuted_axes([1, 0]);
+
+        let scale_x = orig_w as f32 / self.inf_width as f32;
+        let scale_y = orig_h as f32 / self.inf_height as f32;
+
+        let mut candidates = Vec::new();
+        let mut discarded = 0;
+        for row in preds.outer_iter() {
+            let (class_id, score) = row
+                .iter()
+                .skip(4)
+                .copied()
+                .enumerate()
+                .fold((0, f32::MIN), |best, (i, s)| if s > best.1 { (i, s) } else { best });
+
+            if score < self.confidence_threshold {
+                discarded += 1;
+                continue;
+            }
+
+            candidates.push(Detection {
+                bbox: BBox::from_cxcywh(row[0] * scale_x, row[1] * scale_y, row[2] * scale_x, row[3] * scale_y),
+                score,
+                class_id,
+                track_id: None,
+            });
+        }
+
+        (nms(candidates, self.iou_threshold), discarded)
+    }
+
+    /// YOLO-OBB `[1, 4 + C + 1, N]`: cx, cy, w, h, per-class scores, then the
+    /// rotation in radians
+    fn decode_yolo_obb(&self, preds: &Array3<f32>, orig_w: u32, orig_h: u32) -> (Vec<OrientedDetection>, usize) {
+        let preds = preds.index_axis(Axis(0), 0);
+        let preds = preds.permuted_axes([1, 0]);
+
+        let scale_x = orig_w as f32 / self.inf_width as f32;
+        let scale_y = orig_h as f32 / self.inf_height as f32;
+
+        let mut candidates = Vec::new();
+        let mut discarded = 0;
+        for row in preds.outer_iter() {
+            let angle_i = row.len() - 1;
+            let (class_id, score) = row
+                .iter()
+                .take(angle_i)
+                .skip(4)
+                .copied()
+                .enumerate()
+                .fold((0, f32::MIN), |best, (i, s)| if s > best.1 { (i, s) } else { best });
+
+            if score < self.confidence_threshold {
+                discarded += 1;
+                continue;
+            }
+
+            let rbox = RotatedBox { cx: row[0], cy: row[1], w: row[2], h: row[3], angle: row[angle_i] };
+            candidates.push(OrientedDetection {
+                rbox: rbox.scaled(scale_x, scale_y),
+                score,
+                class_id,
+                track_id: None,
+            });
+        }
+
+        let kept = nms_rotated_indices(&candidates, self.iou_threshold)
+            .into_iter()
+            .map(|i| candidates[i])
+            .collect();
+        (kept, discarded)
+    }
+
+    /// Exports with NMS built into the graph, in the layout found at load
+    fn decode_exported_nms(
+        &self,
+        outputs: &ort::session::SessionOutputs,
+        orig_w: u32,
+        orig_h: u32,
+    ) -> Result<(Detections, usize), Box<dyn Error>> {
+        let values = |name: &str| {
+            outputs
+                .get(name)
+                .and_then(numeric_values)
+                .ok_or_else(|| format!("Unreadable output {name}"))
+        };
+
+        // (x1, y1, x2, y2, score, class) rows in model input pixels
+        let rows: Vec<[f32; 6]> = match self.nms_layout.as_ref().ok_or("Output layout not inspected")? {
+            NmsLayout::Split { num, boxes, scores, classes } => {
+                let (boxes, scores, classes) = (values(boxes)?, values(scores)?, values(classes)?);
+                let count = match num {
+                    Some(num) => values(num)?.first().copied().unwrap_or(0.0) as usize,
+                    None => scores.len(),
+                };
+                let count = count.min(scores.len()).min(classes.len()).min(boxes.len() / 4);
+
+                (0..count)
+                    .map(|i| {
+                        let b = &boxes[i * 4..i * 4 + 4];
+                        [b[0], b[1], b[2], b[3], scores[i], classes[i]]
+                    })
+                    .collect()
+            }
+            NmsLayout::BatchRows => numeric_values(output_tensor(outputs, self.output.as_deref())?)
+                .ok_or("Unreadable [N, 7] output")?
+                .chunks_exact(7)
+                .filter(|r| r[0] == 0.0)
+                .map(|r| [r[1], r[2], r[3], r[4], r[5], r[6]])
+                .collect(),
+            NmsLayout::Rows => numeric_values(output_tensor(outputs, self.output.as_deref())?)
+                .ok_or("Unreadable [1, K, 6] output")?
+                .chunks_exact(6)
+                .map(|r| [r[0], r[1], r[2], r[3], r[4], r[5]])
+                .collect(),
+        };
+
+        // Models fed the frame size already scale their boxes to it
+        let (scale_x, scale_y) = if self.frame_pixels {
+            (1.0, 1.0)
+        } else {
+            (orig_w as f32 / self.inf_width as f32, orig_h as f32 / self.inf_height as f32)
+        };
+
+        let (kept, discarded): (Vec<_>, Vec<_>) = rows.into_iter().partition(|r| r[4] >= self.confidence_threshold);
+        let detections = kept
+            .into_iter()
+            .map(|[x1, y1, x2, y2, score, class]| Detection {
+                bbox: BBox { x1: x1 * scale_x, y1: y1 * scale_y, x2: x2 * scale_x, y2: y2 * scale_y },
+                score,
+                class_id: class.max(0.0) as usize,
+                track_id: None,
+            })
+            .collect();
+        Ok((detections, discarded.len()))
+    }
+
+    /// DETR-family `[1, Q, 4]` normalized cxcywh boxes and `[1, Q, C]`
+    /// logits, or a single `[1, Q, 4 + C]` tensor. One object per query, so
+    /// no NMS.
+    fn decode_detr(
+        &self,
+        outputs: &ort::session::SessionOutputs,
+        orig_w: u32,
+        orig_h: u32,
+    ) -> Result<(Detections, usize), Box<dyn Error>> {
+        let mut tensors = Vec::new();
+        for name in outputs.keys() {
+            let Some(value) = outputs.get(name) else { continue };
+            if let Ok(array) = extract_f32(value, self.output_quant)
+                && array.ndim() == 3
+            {
+                tensors.push((name, array.index_axis_move(Axis(0), 0).into_dimensionality::<Ix2>()?));
+            }
+        }
+
+        // Column counts can't tell them apart: 4 classes also make 4 logits
+        let is_boxes = |name: &str| match (&self.config.boxes_output, &self.config.logits_output) {
+            (Some(boxes), _) => name == boxes,
+            (None, Some(logits)) => name != logits,
+            (None, None) => name.contains("box"),
+        };
+
+        let (boxes, logits): (ArrayView2<f32>, ArrayView2<f32>) = match tensors.as_slice() {
+            [(_, single)] if single.ncols() > 4 => (single.slice(s![.., ..4]), single.slice(s![.., 4..])),
+            [(a_name, a), (b_name, b)] => match (is_boxes(a_name), is_boxes(b_name)) {
+                (true, false) => (a.view(), b.view()),
+                (false, true) => (b.view(), a.view()),
+                _ => return Err(format!(
+                    "Can't tell boxes from logits in outputs {a_name} and {b_name}; set boxes_output"
+                ).into()),
+            },
+            _ => return Err("Unsupported DETR outputs".into()),
+        };
+
+        if boxes.ncols() != 4 {
+            return Err(format!("Box output has {} columns, expected 4", boxes.ncols()).into());
+        }
+        if boxes.nrows() != logits.nrows() {
+            return Err("Box and logit query counts differ".into());
+        }
+
+        let num_classes = match (self.config.no_object_class, logits.ncols()) {
+            (true, n) if n > 1 => n - 1,
+            (_, n) => n,
+        };
+
+        let mut detections = Vec::new();
+        let mut discarded = 0;
+        for (bbox, logits) in boxes.outer_iter().zip(logits.outer_iter()) {
+            let mut scores: Vec<f32> = logits.to_vec();
+            match self.config.scoring {
+                DetrScoring::Sigmoid => scores.iter_mut().for_each(|v| *v = sigmoid(*v)),
+                DetrScoring::Softmax => softmax(&mut scores),
+                DetrScoring::Probabilities => {}
+            }
+
+            let (class_id, score) = scores[..num_classes]
+                .iter()
+                .copied()
+                .enumerate()
+                .fold((0, f32::MIN), |best, (i, s)| if s > best.1 { (i, s) } else { best });
+
+            if score < self.confidence_threshold {
+                discarded += 1;
+                continue;
+            }
+
+            detections.push(Detection {
+                bbox: BBox::from_cxcywh(
+                    bbox[0] * orig_w as f32,
+                    bbox[1] * orig_h as f32,
+                    bbox[2] * orig_w as f32,
+                    bbox[3] * orig_h as f32,
+                ),
+                score,
+                class_id,
+                track_id: None,
+            });
+        }
+
+        detections.sort_by(|a, b| b.score.total_cmp(&a.score));
+        Ok((detections, discarded))
+    }
+
+    fn render_detections(&self, detections: &[Detection], width: u32, height: u32) -> Vec<u8> {
+        let mut dt = DrawTarget::new(width as i32, height as i32);
+        let line_width = self.style.line_width(height);
+
+        for det in detections.iter().filter(|d| !self.hidden_classes.contains(&d.class_id)) {
+            // Tracked boxes keep their color across frames, others are colored by class
+            let color = self.style.box_color(det.class_id, det.track_id);
+
+            let mut pb = PathBuilder::new();
+            pb.rect(det.bbox.x1, det.bbox.y1, det.bbox.width(), det.bbox.height());
+
+            dt.stroke(
+                &pb.finish(),
+                &Source::Solid(solid(color)),
+                &StrokeStyle {
+                    width: line_width,
+                    ..Default::default()
+                },
+                &DrawOptions::new(),
+            );
+
+            self.draw_label(&mut dt, det.class_id, det.score, (det.bbox.x1, det.bbox.y1), color, height);
+        }
+
+        draw_target_to_rgba(&dt, width, height)
+    }
+
+    fn render_oriented(&self, detections: &[OrientedDetection], width: u32, height: u32) -> Vec<u8> {
+        let mut dt = DrawTarget::new(width as i32, height as i32);
+        let line_width = self.style.line_width(height);
+
+        for det in detections.iter().filter(|d| !self.hidden_classes.contains(&d.class_id)) {
+            let color = self.style.box_color(det.class_id, det.track_id);
+
+            let corners = det.rbox.corners();
+            let [first, rest @ ..] = corners;
+            let mut pb = PathBuilder::new();
+            pb.move_to(first.0, first.1);
+            for (x, y) in rest {
+                pb.line_to(x, y);
+            }
+            pb.close();
+
+            dt.stroke(
+                &pb.finish(),
+                &Source::Solid(solid(color)),
+                &StrokeStyle {
+                    width: line_width,
+                    ..Default::default()
+                },
+                &DrawOptions::new(),
+            );
+
+            // Above the topmost corner
+            let top = corners.into_iter().fold(first, |a, b| if b.1 < a.1 { b } else { a });
+            self.draw_label(&mut dt, det.class_id, det.score, top, color, height);
+        }
+
+        draw_target_to_rgba(&dt, width, height)
+    }
+
+    /// Class id and score just above `(x, y)`, when scores are shown
+    fn draw_label(&self, dt: &mut DrawTarget, class_id: usize, score: f32, (x, y): (f32, f32), color: (u8, u8, u8), height: u32) {
+        if !self.style.show_confidence() {
+            return;
+        }
+        draw_text(
+            dt,
+            &format!("{class_id} {:.0}%", score * 100.0),
+            x,
+            y - self.style.line_width(height) * 2.0,
+            self.style.font_size(height),
+            solid(color),
+        );
+    }
+}
+
+impl VisionTask for ObjectTask {
+    fn decoding(&self) -> Option<String> {
+        self.nms_layout.as_ref().map(|layout| format!("exported NMS {layout:?}"))
+    }
+
+    fn preprocess(
+        &self,
+        rgba: &[u8],
+        width: u32,
+        height: u32,
+        region: &BBox,
+    ) -> Array4<f32> {
+        sample_region(rgba, width, height, region, self.inf_width, self.inf_height)
+    }
+
+    fn postprocess(
+        &self,
+        outputs: &ort::session::SessionOutputs,
+        orig_w: u32,
+        orig_h: u32,
+    ) -> Result<(TaskResult, usize), Box<dyn Error>> {
+        if self.config.format == BoxFormat::Detr {
+            let (detections, discarded) = self.decode_detr(outputs, orig_w, orig_h)?;
+            return Ok((TaskResult::Detections(detections), discarded));
+        }
+        if self.nms_layout.is_some() {
+            let (detections, discarded) = self.decode_exported_nms(outputs, orig_w, orig_h)?;
+            return Ok((TaskResult::Detections(detections), discarded));
+        }
+
+        let tensor = output_tensor(outputs, self.output.as_deref())?;
+
+        let array = extract_f32(tensor, self.output_quant)?.into_owned();
+
+        match (self.config.format, array.ndim()) {
+            (BoxFormat::Yolo, 3) => {
+                let preds = array.into_dimensionality::<ndarray::Ix3>()?;
+                let (detections, discarded) = self.decode_yolo(&preds, orig_w, orig_h);
+                Ok((TaskResult::Detections(detections), discarded))
+            }
+
+            (BoxFormat::YoloObb, 3) => {
+                let preds = array.into_dimensionality::<ndarray::Ix3>()?;
+                let (detections, discarded) = self.decode_yolo_obb(&preds, orig_w, orig_h);
+                Ok((TaskResult::OrientedDetections(detections), discarded))
+            }
+
+            _ => Err("Unsupported output shape".into()),
+        }
+    }
+
+    fn render(
+        &self,
+        result: &TaskResult,
+        width: u32,
+        height: u32,
+    ) -> Vec<u8> {
+        match result {
+            TaskResult::Detections(detections) => self.render_detections(detections, width, height),
+            TaskResult::OrientedDetections(detections) => self.render_oriented(detections, width, height),
+            _ => unreachable!(),
+        }
+    }
+
+    fn apply_settings(&mut self, settings: &TaskSettings) {
+        self.confidence_threshold = settings.confidence_threshold;
+        self.iou_threshold = settings.iou_threshold;
+        self.hidden_classes = settings.hidden_classes.clone();
+    }
+
+    fn inspect_outputs(&mut self, outputs: &[Outlet]) -> Result<(), Box<dyn Error>> {
+        self.nms_layout = match self.config.format {
+            BoxFormat::ExportedNms => Some(NmsLayout::detect(outputs, self.output.as_deref())?),
+            // Raw YOLO heads never match these layouts
+            BoxFormat::Yolo => NmsLayout::detect(outputs, self.output.as_deref()).ok(),
+            BoxFormat::YoloObb | BoxFormat::Detr => None,
+        };
+        Ok(())
+    }
+}
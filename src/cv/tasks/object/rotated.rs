@@ -0,0 +1,205 @@
+use serde::{Deserialize, Serialize};
+
+use super::{BBox, Detection};
+
+/// Rectangle of size `w x h` centered at (cx, cy), rotated by `angle`
+/// radians (clockwise, since y points down)
+#[derive(Clone, Debug, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct RotatedBox {
+    pub cx: f32,
+    pub cy: f32,
+    pub w: f32,
+    pub h: f32,
+    pub angle: f32,
+}
+
+impl RotatedBox {
+    /// Corners in winding order
+    pub fn corners(&self) -> [(f32, f32); 4] {
+        let (sin, cos) = self.angle.sin_cos();
+        let (ux, uy) = (self.w / 2.0 * cos, self.w / 2.0 * sin);
+        let (vx, vy) = (-self.h / 2.0 * sin, self.h / 2.0 * cos);
+
+        [
+            (self.cx - ux - vx, self.cy - uy - vy),
+            (self.cx + ux - vx, self.cy + uy - vy),
+            (self.cx + ux + vx, self.cy + uy + vy),
+            (self.cx - ux + vx, self.cy - uy + vy),
+        ]
+    }
+
+    /// Smallest axis-aligned box containing the corners
+    pub fn bounding(&self) -> BBox {
+        self.corners().iter().fold(
+            BBox { x1: f32::MAX, y1: f32::MAX, x2: f32::MIN, y2: f32::MIN },
+            |b, &(x, y)| BBox { x1: b.x1.min(x), y1: b.y1.min(y), x2: b.x2.max(x), y2: b.y2.max(y) },
+        )
+    }
+
+    /// Map from model input to frame pixels. With unequal scales the result
+    /// is the rectangle spanned by the scaled box axes.
+    pub fn scaled(&self, sx: f32, sy: f32) -> Self {
+        let (sin, cos) = self.angle.sin_cos();
+        Self {
+            cx: self.cx * sx,
+            cy: self.cy * sy,
+            w: self.w * (cos * sx).hypot(sin * sy),
+            h: self.h * (sin * sx).hypot(cos * sy),
+            angle: (sin * sy).atan2(cos * sx),
+        }
+    }
+
+    pub fn area(&self) -> f32 {
+        self.w.max(0.0) * self.h.max(0.0)
+    }
+
+    pub fn iou(&self, other: &RotatedBox) -> f32 {
+        let inter = polygon_area(&clip(&self.corners(), &other.corners()));
+        let union = self.area() + other.area() - inter;
+        if union <= 0.0 { 0.0 } else { inter / union }
+    }
+}
+
+#[derive(Clone, Debug, Copy, Serialize, Deserialize)]
+pub struct OrientedDetection {
+    pub rbox: RotatedBox,
+    pub score: f32,
+    pub class_id: usize,
+    pub track_id: Option<u32>,
+}
+
+impl OrientedDetection {
+    /// Axis-aligned view, used for tracking
+    pub fn detection(&self) -> Detection {
+        Detection {
+            bbox: self.rbox.bounding(),
+            score: self.score,
+            class_id: self.class_id,
+            track_id: self.track_id,
+        }
+    }
+}
+
+fn cross(o: (f32, f32), a: (f32, f32), b: (f32, f32)) -> f32 {
+    (a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0)
+}
+
+/// Signed shoelace area, positive for counter-clockwise in y-up terms
+fn signed_area(poly: &[(f32, f32)]) -> f32 {
+    let n = poly.len();
+    (0..n)
+        .map(|i| {
+            let (a, b) = (poly[i], poly[(i + 1) % n]);
+            a.0 * b.1 - b.0 * a.1
+        })
+        .sum::<f32>()
+        / 2.0
+}
+
+fn polygon_area(poly: &[(f32, f32)]) -> f32 {
+    if poly.len() < 3 { 0.0 } else { signed_area(poly).abs() }
+}
+
+/// Sutherland-Hodgman: `subject` clipped to the convex polygon `clipper`
+fn clip(subject: &[(f32, f32)], clipper: &[(f32, f32)]) -> Vec<(f32, f32)> {
+    let orientation = signed_area(clipper);
+    if orientation == 0.0 {
+        // Degenerate clipper: nothing is inside it
+        return Vec::new();
+    }
+    let orientation = orientation.signum();
+    let mut output = subject.to_vec();
+
+    for i in 0..clipper.len() {
+        if output.is_empty() {
+            break;
+        }
+        let (a, b) = (clipper[i], clipper[(i + 1) % clipper.len()]);
+        let inside = |p: (f32, f32)| cross(a, b, p) * orientation >= 0.0;
+        let intersect = |p: (f32, f32), q: (f32, f32)| {
+            let (dp, dq) = (cross(a, b, p), cross(a, b, q));
+            let t = dp / (dp - dq);
+            (p.0 + t * (q.0 - p.0), p.1 + t * (q.1 - p.1))
+        };
+
+        let input = std::mem::take(&mut output);
+        for j in 0..input.len() {
+            let (p, q) = (input[j], input[(j + 1) % input.len()]);
+            match (inside(p), inside(q)) {
+                (true, true) => output.push(q),
+                (true, false) => output.push(intersect(p, q)),
+                (false, true) => {
+                    output.push(intersect(p, q));
+                    output.push(q);
+                }
+                (false, false) => {}
+            }
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, SQRT_2};
+
+    use super::*;
+
+    fn rbox(cx: f32, cy: f32, w: f32, h: f32, angle: f32) -> RotatedBox {
+        RotatedBox { cx, cy, w, h, angle }
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-4, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn identical_boxes() {
+        let a = rbox(10.0, 20.0, 4.0, 2.0, 0.3);
+        assert_close(a.iou(&a), 1.0);
+    }
+
+    #[test]
+    fn disjoint_boxes() {
+        let a = rbox(0.0, 0.0, 2.0, 2.0, 0.0);
+        let b = rbox(10.0, 0.0, 2.0, 2.0, 0.5);
+        assert_close(a.iou(&b), 0.0);
+        assert!(clip(&a.corners(), &b.corners()).is_empty());
+    }
+
+    #[test]
+    fn square_rotated_by_90_degrees() {
+        let a = rbox(0.0, 0.0, 2.0, 2.0, 0.0);
+        assert_close(a.iou(&rbox(0.0, 0.0, 2.0, 2.0, FRAC_PI_2)), 1.0);
+
+        // A 2x4 rectangle crosses itself turned upright in a 2x2 square
+        let a = rbox(0.0, 0.0, 4.0, 2.0, 0.0);
+        let b = rbox(0.0, 0.0, 4.0, 2.0, FRAC_PI_2);
+        assert_close(polygon_area(&clip(&a.corners(), &b.corners())), 4.0);
+        assert_close(a.iou(&b), 4.0 / 12.0);
+    }
+
+    #[test]
+    fn partial_overlap() {
+        // Shifted by half a side: 2 of the union's 6
+        let a = rbox(0.0, 0.0, 2.0, 2.0, 0.0);
+        let b = rbox(1.0, 0.0, 2.0, 2.0, 0.0);
+        assert_close(polygon_area(&clip(&a.corners(), &b.corners())), 2.0);
+        assert_close(a.iou(&b), 1.0 / 3.0);
+
+        // Turned by 45 degrees the overlap is a regular octagon
+        let b = rbox(0.0, 0.0, 2.0, 2.0, FRAC_PI_4);
+        assert_close(polygon_area(&clip(&a.corners(), &b.corners())), 8.0 * (SQRT_2 - 1.0));
+        assert_close(a.iou(&b), 1.0 / SQRT_2);
+    }
+
+    #[test]
+    fn zero_area_box() {
+        let a = rbox(0.0, 0.0, 2.0, 2.0, 0.0);
+        let flat = rbox(0.0, 0.0, 2.0, 0.0, 0.2);
+        assert_close(flat.area(), 0.0);
+        assert_close(a.iou(&flat), 0.0);
+        assert_close(flat.iou(&a), 0.0);
+        assert_close(flat.iou(&flat), 0.0);
+    }
+}
@@ -1,25 +1,61 @@
+mod confidence;
 mod constants;
+mod heatmap;
+mod smoothing;
 
 use std::error::Error;
-use crate::{config::{InferenceGenericConfig, PoseConfig}, cv::tasks::pose::constants::KPT_START};
+use crate::{config::{InferenceGenericConfig, PoseConfig, QuantConfig}, cv::tasks::pose::constants::KPT_START};
 
-use super::{VisionTask, TaskResult};
+use super::{VisionTask, TaskResult, TaskSettings, common::{draw_target_to_rgba, draw_text, extract_f32, output_tensor, sample_region}, object::{BBox, Detection, nms_indices}, style::{OverlayStyle, solid}};
 use raqote::{
     DrawOptions, DrawTarget, LineJoin, PathBuilder,
-    SolidSource, Source, StrokeStyle,
+    Source, StrokeStyle,
 };
 use ndarray::{Array4, Axis};
+use ort::value::Outlet;
+use serde::{Deserialize, Serialize};
 use constants::SKELETON;
+use heatmap::{gaussian_blur, refine};
+
+pub use confidence::{ConfidenceStats, ConfidenceTracker, KeypointConfidence};
+pub use constants::COCO_KEYPOINT_NAMES;
+pub use smoothing::{JitterStats, PoseSmoother};
 
 pub type Keypoints = [Option<(f32, f32, f32)>; 17];
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Person {
+    pub bbox: BBox,
+    pub score: f32,
+    pub track_id: Option<u32>,
+    pub keypoints: Keypoints,
+}
+
+impl Person {
+    pub fn detection(&self) -> Detection {
+        Detection {
+            bbox: self.bbox,
+            score: self.score,
+            class_id: 0,
+            track_id: self.track_id,
+        }
+    }
+}
+
 #[derive(Debug)]
 
 pub struct PoseTask {
     config: PoseConfig,
     inf_width: usize,
     inf_height: usize,
+    output: Option<String>,
+    output_quant: Option<QuantConfig>,
     confidence_threshold: f32,
+    iou_threshold: f32,
+    hidden_keypoints: Vec<usize>,
+    style: OverlayStyle,
+    /// The decoded output is a heatmap rather than YOLO rows
+    heatmap: bool,
 }
 
 impl PoseTask {
@@ -28,7 +64,13 @@ impl PoseTask {
             config: pose_config.clone(),
             inf_width: generics.inf_width,
             inf_height: generics.inf_height,
+            output: generics.output.clone(),
+            output_quant: generics.output_quant,
             confidence_threshold: generics.confidence_threshold,
+            iou_threshold: generics.iou_threshold,
+            hidden_keypoints: generics.hidden_keypoints.clone(),
+            style: OverlayStyle::new(&generics.overlay),
+            heatmap: false,
         }
     }
 
@@ -37,7 +79,7 @@ impl PoseTask {
         heatmaps: &Array4<f32>,
         orig_w: u32,
         orig_h: u32,
-    ) -> Keypoints {
+    ) -> Person {
         let mut keypoints: Keypoints = [None; 17];
         let maps = heatmaps.index_axis(Axis(0), 0);
 
@@ -47,9 +89,21 @@ impl PoseTask {
         let scale_x = orig_w as f32 / self.inf_width as f32;
         let scale_y = orig_h as f32 / self.inf_height as f32;
 
+        let mut blurred = Vec::new();
+
         for &k in &self.config.keep_keypoints {
             let map = maps.index_axis(Axis(0), k);
-            let slice = map.as_slice().unwrap();
+            let raw = map.as_slice().unwrap();
+
+            let slice = match self.config.heatmap_blur {
+                Some(sigma) => {
+                    blurred.clear();
+                    blurred.extend_from_slice(raw);
+                    gaussian_blur(&mut blurred, hm_w, hm_h, sigma);
+                    &blurred[..]
+                }
+                None => raw,
+            };
 
             let (mut max_val, mut max_idx) = (f32::MIN, 0);
             for (i, &v) in slice.iter().enumerate() {
@@ -61,14 +115,41 @@ impl PoseTask {
 
             let y = max_idx / hm_w;
             let x = max_idx % hm_w;
+            let (x, y) = refine(slice, hm_w, hm_h, x, y, self.config.heatmap_decoding);
 
-            let x_img = (x as f32 * self.inf_width as f32 / hm_w as f32) * scale_x;
-            let y_img = (y as f32 * self.inf_height as f32 / hm_h as f32) * scale_y;
+            let x_img = (x * self.inf_width as f32 / hm_w as f32) * scale_x;
+            let y_img = (y * self.inf_height as f32 / hm_h as f32) * scale_y;
 
-            keypoints[k] = Some((x_img, y_img, max_val));
+            // Blurring flattens the peak, so report the unblurred confidence
+            keypoints[k] = Some((x_img, y_img, raw[max_idx]));
         }
 
-        keypoints
+        self.person_from_keypoints(keypoints)
+    }
+
+    /// Heatmap models have no box output, so bound the confident keypoints
+    fn person_from_keypoints(&self, keypoints: Keypoints) -> Person {
+        let mut bbox = BBox { x1: f32::MAX, y1: f32::MAX, x2: f32::MIN, y2: f32::MIN };
+        let (mut total, mut count) = (0.0, 0);
+
+        for &(x, y, c) in keypoints.iter().flatten() {
+            total += c;
+            count += 1;
+            if c >= self.confidence_threshold {
+                bbox = BBox { x1: bbox.x1.min(x), y1: bbox.y1.min(y), x2: bbox.x2.max(x), y2: bbox.y2.max(y) };
+            }
+        }
+
+        if bbox.x1 > bbox.x2 {
+            bbox = BBox::default();
+        }
+
+        Person {
+            bbox,
+            score: if count > 0 { total / count as f32 } else { 0.0 },
+            track_id: None,
+            keypoints,
+        }
     }
 
     fn decode_yolo_pose(
@@ -76,7 +157,7 @@ impl PoseTask {
         preds: &ndarray::Array3<f32>,
         orig_w: u32,
         orig_h: u32,
-    ) -> Result<Keypoints, Box<dyn Error>> {
+    ) -> (Vec<Person>, usize) {
 
         // shape: [1, 56, 8400]
         let preds = preds.index_axis(ndarray::Axis(0), 0);
@@ -84,75 +165,125 @@ impl PoseTask {
         // transpose to [8400, 56]
         let preds = preds.permuted_axes([1, 0]);
 
-        let mut best_conf = 0.0;
-        let mut best_row = None;
+        let scale_x = orig_w as f32 / self.inf_width as f32;
+        let scale_y = orig_h as f32 / self.inf_height as f32;
 
-        for row in preds.outer_iter() {
-            let conf = row[4];
+        let mut candidates = Vec::new();
+        let mut rows = Vec::new();
+        let mut discarded = 0;
 
-            if conf > best_conf {
-                best_conf = conf;
-                best_row = Some(row);
+        for (i, row) in preds.outer_iter().enumerate() {
+            let conf = row[4];
+            if conf < self.confidence_threshold {
+                discarded += 1;
+                continue;
             }
-        }
-
-        let row = best_row.ok_or("No detections")?;
 
-        let mut keypoints: Keypoints = [None; 17];
+            candidates.push(Detection {
+                bbox: BBox::from_cxcywh(row[0] * scale_x, row[1] * scale_y, row[2] * scale_x, row[3] * scale_y),
+                score: conf,
+                class_id: 0,
+                track_id: None,
+            });
+            rows.push(i);
+        }
 
-        let scale_x = orig_w as f32 / self.inf_width as f32;
-        let scale_y = orig_h as f32 / self.inf_height as f32;
         let kpt_start = KPT_START; // after bbox + obj + class
 
-        for &k in &self.config.keep_keypoints {
-            let base = kpt_start + k * 3;
+        let people = nms_indices(&candidates, self.iou_threshold)
+            .into_iter()
+            .map(|i| {
+                let row = preds.index_axis(Axis(0), rows[i]);
+                let mut keypoints: Keypoints = [None; 17];
 
-            let x = row[base] * scale_x as f32;
-            let y = row[base + 1] * scale_y as f32;
-            let conf = row[base + 2];
+                for &k in &self.config.keep_keypoints {
+                    let base = kpt_start + k * 3;
 
-            keypoints[k] = Some((x, y, conf));
-        }
+                    let x = row[base] * scale_x;
+                    let y = row[base + 1] * scale_y;
+                    let conf = row[base + 2];
+
+                    keypoints[k] = Some((x, y, conf));
+                }
 
-        Ok(keypoints)
+                Person {
+                    bbox: candidates[i].bbox,
+                    score: candidates[i].score,
+                    track_id: None,
+                    keypoints,
+                }
+            })
+            .collect();
+        (people, discarded)
     }
 
-    fn render_pose(&self, keypoints: &Keypoints, width: u32, height: u32) -> Vec<u8> {
+    fn render_pose(&self, people: &[Person], width: u32, height: u32) -> Vec<u8> {
         // ----- Draw -----
         let mut dt = DrawTarget::new(width as i32, height as i32);
-        self.draw_skeleton(&mut dt, &keypoints);
+        for person in people {
+            self.draw_skeleton(&mut dt, person, height);
+        }
 
         // ----- Extract RGBA back out -----
-        let data = dt.get_data();
-        let mut out = Vec::with_capacity((width * height * 4) as usize);
-
-        for px in data {
-            out.push((px >> 16) as u8); // R
-            out.push((px >> 8) as u8);  // G
-            out.push(*px as u8);        // B
-            out.push((px >> 24) as u8); // A
-        }
-        out
+        draw_target_to_rgba(&dt, width, height)
     }
 
-    fn draw_skeleton(&self, dt: &mut DrawTarget, keypoints: &Keypoints) {
-        for &(i, j) in SKELETON {
+    fn draw_skeleton(&self, dt: &mut DrawTarget, person: &Person, height: u32) {
+        let keypoints = &person.keypoints;
+        let line_width = self.style.line_width(height);
+        let radius = self.style.point_radius(height);
+        let font_size = self.style.font_size(height);
+
+        // Tracked people keep one color for their limbs and box across frames
+        let person_color = self.style.box_color(0, person.track_id);
+
+        if person.track_id.is_some() {
+            let mut pb = PathBuilder::new();
+            pb.rect(person.bbox.x1, person.bbox.y1, person.bbox.width(), person.bbox.height());
+
+            dt.stroke(
+                &pb.finish(),
+                &Source::Solid(solid(person_color)),
+                &StrokeStyle {
+                    width: line_width,
+                    ..Default::default()
+                },
+                &DrawOptions::new(),
+            );
+
+            if self.style.show_confidence() {
+                draw_text(
+                    dt,
+                    &format!("{:.0}%", person.score * 100.0),
+                    person.bbox.x1,
+                    person.bbox.y1 - line_width * 2.0,
+                    font_size,
+                    solid(person_color),
+                );
+            }
+        }
+
+        for (limb, &(i, j)) in SKELETON.iter().enumerate() {
             if let (Some((x1, y1, c1)), Some((x2, y2, c2))) =
                 (keypoints[i], keypoints[j])
             {
                 if c1 < self.confidence_threshold || c2 < self.confidence_threshold {
                     continue;
                 }
+                if self.hidden_keypoints.contains(&i) || self.hidden_keypoints.contains(&j) {
+                    continue;
+                }
 
                 let mut pb = PathBuilder::new();
                 pb.move_to(x1, y1);
                 pb.line_to(x2, y2);
 
+                let color = self.style.limb_color(limb).unwrap_or(person_color);
                 dt.stroke(
                     &pb.finish(),
-                    &Source::Solid(SolidSource { r: 255, g: 0, b: 0, a: 255 }),
+                    &Source::Solid(solid(color)),
                     &StrokeStyle {
-                        width: 2.0,
+                        width: line_width,
                         join: LineJoin::Round,
                         ..Default::default()
                     },
@@ -161,93 +292,76 @@ impl PoseTask {
             }
         }
 
-        for &(x, y, c) in keypoints.iter().flatten() {
-            if c < self.confidence_threshold {
+        for (k, keypoint) in keypoints.iter().enumerate() {
+            let Some((x, y, c)) = *keypoint else {
+                continue;
+            };
+            if c < self.confidence_threshold || self.hidden_keypoints.contains(&k) {
                 continue;
             }
 
+            let color = solid(self.style.keypoint_color(k, (0, 255, 0)));
+
             let mut pb = PathBuilder::new();
-            pb.arc(x, y, 4.0, 0.0, std::f32::consts::TAU);
+            pb.arc(x, y, radius, 0.0, std::f32::consts::TAU);
 
             dt.fill(
                 &pb.finish(),
-                &Source::Solid(SolidSource { r: 0, g: 255, b: 0, a: 255 }),
+                &Source::Solid(color),
                 &DrawOptions::new(),
             );
+
+            if let Some(label) = self.style.keypoint_label(COCO_KEYPOINT_NAMES[k], c) {
+                draw_text(dt, &label, x + radius * 1.5, y - radius, font_size, color);
+            }
         }
     }
 }
 
 impl VisionTask for PoseTask {
+    fn decoding(&self) -> Option<String> {
+        if !self.heatmap {
+            return None;
+        }
+        let blur = self.config.heatmap_blur
+            .map(|sigma| format!(", blur σ={sigma}"))
+            .unwrap_or_default();
+        Some(format!("heatmap {:?}{blur}", self.config.heatmap_decoding))
+    }
+
     fn preprocess(
         &self,
         rgba: &[u8],
         width: u32,
         height: u32,
+        region: &BBox,
     ) -> Array4<f32> {
-
-        let w = self.inf_width;
-        let h = self.inf_height;
-
-        let mut input = Array4::<f32>::zeros((1, 3, h, w));
-        let out = input.as_slice_mut().unwrap();
-
-        let hw = h * w;
-        let scale = 1.0 / 255.0;
-
-        let x_ratio = width as f32 / w as f32;
-        let y_ratio = height as f32 / h as f32;
-
-        for y in 0..h {
-            let src_y = (y as f32 * y_ratio) as usize;
-
-            for x in 0..w {
-                let src_x = (x as f32 * x_ratio) as usize;
-
-                let src_i = ((src_y * width as usize + src_x) * 4) as usize;
-                let dst_i = y * w + x;
-
-                let r = rgba[src_i];
-                let g = rgba[src_i + 1];
-                let b = rgba[src_i + 2];
-
-                out[dst_i] = r as f32 * scale;
-                out[hw + dst_i] = g as f32 * scale;
-                out[2 * hw + dst_i] = b as f32 * scale;
-            }
-        }
-
-        input
+        sample_region(rgba, width, height, region, self.inf_width, self.inf_height)
     }
 
     fn postprocess(
         &self,
         outputs: &ort::session::SessionOutputs,
-        output_name: &str,
         orig_w: u32,
         orig_h: u32,
-    ) -> Result<TaskResult, Box<dyn Error>> {
-        let tensor = outputs
-            .get(output_name)
-            .ok_or("Missing output tensor")?;
+    ) -> Result<(TaskResult, usize), Box<dyn Error>> {
+        let tensor = output_tensor(outputs, self.output.as_deref())?;
 
-        let array = tensor
-            .try_extract_array::<f32>()?
-            .into_owned();
+        let array = extract_f32(tensor, self.output_quant)?.into_owned();
 
         match array.ndim() {
             // Heatmap model
             4 => {
                 let heatmaps = array.into_dimensionality::<ndarray::Ix4>()?;
-                let keypoints = self.decode_heatmap_pose(&heatmaps, orig_w, orig_h);
-                Ok(TaskResult::Pose(keypoints))
+                let person = self.decode_heatmap_pose(&heatmaps, orig_w, orig_h);
+                Ok((TaskResult::Pose(vec![person]), 0))
             }
 
             // YOLO model
             3 => {
                 let preds = array.into_dimensionality::<ndarray::Ix3>()?;
-                let keypoints = self.decode_yolo_pose(&preds, orig_w, orig_h)?;
-                Ok(TaskResult::Pose(keypoints))
+                let (people, discarded) = self.decode_yolo_pose(&preds, orig_w, orig_h);
+                Ok((TaskResult::Pose(people), discarded))
             }
 
             _ => Err("Unsupported output shape".into()),
@@ -261,8 +375,25 @@ impl VisionTask for PoseTask {
         height: u32,
     ) -> Vec<u8> {
         match result {
-            TaskResult::Pose(keypoints) => self.render_pose(keypoints, width, height),
+            TaskResult::Pose(people) => self.render_pose(people, width, height),
             _ => unreachable!(),
         }
     }
+
+    fn apply_settings(&mut self, settings: &TaskSettings) {
+        self.confidence_threshold = settings.confidence_threshold;
+        self.iou_threshold = settings.iou_threshold;
+        self.hidden_keypoints = settings.hidden_keypoints.clone();
+    }
+
+    fn inspect_outputs(&mut self, outputs: &[Outlet]) -> Result<(), Box<dyn Error>> {
+        let outlet = match self.output.as_deref() {
+            Some(name) => outputs.iter().find(|o| o.name() == name),
+            None => outputs.first(),
+        };
+        self.heatmap = outlet
+            .and_then(|o| o.dtype().tensor_shape())
+            .is_some_and(|shape| shape.len() == 4);
+        Ok(())
+    }
 }
\ No newline at end of file
@@ -0,0 +1,125 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use super::Person;
+
+/// Share of the recent window a keypoint must spend below threshold to warn
+const WARN_RATIO: f32 = 0.5;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct KeypointConfidence {
+    pub index: usize,
+    /// Mean over the recent window, of the most confident person per frame
+    pub mean: f32,
+    /// Share of recent frames where the keypoint was below threshold
+    pub low_ratio: f32,
+    /// Below threshold for most of the recent window
+    pub warning: bool,
+    /// Times the keypoint was trimmed since the last reset
+    pub trimmed_total: u64,
+}
+
+/// Detections and keypoints dropped by the confidence threshold, this frame
+/// and overall
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ConfidenceStats {
+    /// Detections, or YOLO pose rows, below threshold in this frame
+    pub discarded: usize,
+    pub discarded_total: u64,
+    /// Keypoints below threshold in this frame, over all people
+    pub trimmed: usize,
+    pub trimmed_total: u64,
+    /// Only keypoints the model reports
+    pub keypoints: Vec<KeypointConfidence>,
+}
+
+/// Highest confidence per keypoint in one frame; unset when nobody had it
+type FrameSample = [Option<f32>; 17];
+
+/// Per-keypoint confidence over a sliding window of frames
+#[derive(Debug)]
+pub struct ConfidenceTracker {
+    threshold: f32,
+    window: usize,
+    recent: VecDeque<FrameSample>,
+    trimmed_total: [u64; 17],
+    discarded_total: u64,
+}
+
+impl ConfidenceTracker {
+    pub fn new(window: usize, threshold: f32) -> Self {
+        Self {
+            threshold,
+            window: window.max(1),
+            recent: VecDeque::new(),
+            trimmed_total: [0; 17],
+            discarded_total: 0,
+        }
+    }
+
+    pub fn set_threshold(&mut self, threshold: f32) {
+        self.threshold = threshold;
+    }
+
+    pub fn reset(&mut self) {
+        self.recent.clear();
+        self.trimmed_total = [0; 17];
+        self.discarded_total = 0;
+    }
+
+    /// `discarded` candidates never made it to `people`
+    pub fn update(&mut self, people: &[Person], discarded: usize) -> ConfidenceStats {
+        self.discarded_total += discarded as u64;
+
+        let mut sample: FrameSample = [None; 17];
+        let mut trimmed = 0;
+
+        for person in people {
+            for (k, keypoint) in person.keypoints.iter().enumerate() {
+                let Some((_, _, c)) = *keypoint else {
+                    continue;
+                };
+                if c < self.threshold {
+                    trimmed += 1;
+                    self.trimmed_total[k] += 1;
+                }
+                sample[k] = Some(sample[k].map_or(c, |best| best.max(c)));
+            }
+        }
+
+        if self.recent.len() == self.window {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(sample);
+
+        let keypoints = (0..17)
+            .filter_map(|k| {
+                let values: Vec<f32> = self.recent.iter().filter_map(|s| s[k]).collect();
+                if values.is_empty() && self.trimmed_total[k] == 0 {
+                    return None;
+                }
+
+                let mean = values.iter().sum::<f32>() / values.len().max(1) as f32;
+                let low = values.iter().filter(|&&c| c < self.threshold).count();
+                let low_ratio = low as f32 / self.recent.len() as f32;
+
+                Some(KeypointConfidence {
+                    index: k,
+                    mean,
+                    low_ratio,
+                    warning: low_ratio > WARN_RATIO,
+                    trimmed_total: self.trimmed_total[k],
+                })
+            })
+            .collect();
+
+        ConfidenceStats {
+            discarded,
+            discarded_total: self.discarded_total,
+            trimmed,
+            trimmed_total: self.trimmed_total.iter().sum(),
+            keypoints,
+        }
+    }
+}
@@ -29,24 +29,24 @@ pub const SKELETON: &[(usize, usize)] = &[
 // 15: left_ankle
 // 16: right_ankle
 
-// pub const COCO_KEYPOINT_NAMES: [&str; 17] = [
-//     "nose",
-//     "left_eye",
-//     "right_eye",
-//     "left_ear",
-//     "right_ear",
-//     "left_shoulder",
-//     "right_shoulder",
-//     "left_elbow",
-//     "right_elbow",
-//     "left_wrist",
-//     "right_wrist",
-//     "left_hip",
-//     "right_hip",
-//     "left_knee",
-//     "right_knee",
-//     "left_ankle",
-//     "right_ankle",
-// ];
+pub const COCO_KEYPOINT_NAMES: [&str; 17] = [
+    "nose",
+    "left_eye",
+    "right_eye",
+    "left_ear",
+    "right_ear",
+    "left_shoulder",
+    "right_shoulder",
+    "left_elbow",
+    "right_elbow",
+    "left_wrist",
+    "right_wrist",
+    "left_hip",
+    "right_hip",
+    "left_knee",
+    "right_knee",
+    "left_ankle",
+    "right_ankle",
+];
 
 pub const KPT_START: usize = 5; 
\ No newline at end of file
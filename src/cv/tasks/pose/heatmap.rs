@@ -0,0 +1,92 @@
+use crate::config::HeatmapDecoding;
+
+/// Separable Gaussian blur of a single `w x h` heatmap, in place
+pub fn gaussian_blur(map: &mut [f32], w: usize, h: usize, sigma: f32) {
+    let radius = (3.0 * sigma).ceil() as isize;
+    let kernel: Vec<f32> = (-radius..=radius)
+        .map(|i| (-(i * i) as f32 / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let norm: f32 = kernel.iter().sum();
+
+    let mut tmp = vec![0.0; map.len()];
+
+    // Horizontal pass, clamping at the borders
+    for y in 0..h {
+        for x in 0..w {
+            let mut acc = 0.0;
+            for (k, weight) in kernel.iter().enumerate() {
+                let sx = (x as isize + k as isize - radius).clamp(0, w as isize - 1) as usize;
+                acc += map[y * w + sx] * weight;
+            }
+            tmp[y * w + x] = acc / norm;
+        }
+    }
+
+    // Vertical pass
+    for y in 0..h {
+        for x in 0..w {
+            let mut acc = 0.0;
+            for (k, weight) in kernel.iter().enumerate() {
+                let sy = (y as isize + k as isize - radius).clamp(0, h as isize - 1) as usize;
+                acc += tmp[sy * w + x] * weight;
+            }
+            map[y * w + x] = acc / norm;
+        }
+    }
+}
+
+/// Sub-pixel location of the peak at grid cell `(x, y)`
+pub fn refine(
+    map: &[f32],
+    w: usize,
+    h: usize,
+    x: usize,
+    y: usize,
+    method: HeatmapDecoding,
+) -> (f32, f32) {
+    let at = |x: usize, y: usize| map[y * w + x];
+
+    match method {
+        HeatmapDecoding::Argmax => (x as f32, y as f32),
+
+        HeatmapDecoding::QuarterOffset => {
+            let mut fx = x as f32;
+            let mut fy = y as f32;
+            if x > 0 && x + 1 < w {
+                fx += 0.25 * (at(x + 1, y) - at(x - 1, y)).signum();
+            }
+            if y > 0 && y + 1 < h {
+                fy += 0.25 * (at(x, y + 1) - at(x, y - 1)).signum();
+            }
+            (fx, fy)
+        }
+
+        HeatmapDecoding::Dark => {
+            // Needs two cells of context on each side for the second derivatives
+            if x < 2 || y < 2 || x + 2 >= w || y + 2 >= h {
+                return (x as f32, y as f32);
+            }
+
+            let l = |x: usize, y: usize| at(x, y).max(1e-10).ln();
+            let c = l(x, y);
+
+            let dx = 0.5 * (l(x + 1, y) - l(x - 1, y));
+            let dy = 0.5 * (l(x, y + 1) - l(x, y - 1));
+            let dxx = 0.25 * (l(x + 2, y) - 2.0 * c + l(x - 2, y));
+            let dyy = 0.25 * (l(x, y + 2) - 2.0 * c + l(x, y - 2));
+            let dxy = 0.25
+                * (l(x + 1, y + 1) - l(x + 1, y - 1) - l(x - 1, y + 1) + l(x - 1, y - 1));
+
+            let det = dxx * dyy - dxy * dxy;
+            if det.abs() < f32::EPSILON {
+                return (x as f32, y as f32);
+            }
+
+            // offset = -H^-1 * gradient
+            let ox = -(dyy * dx - dxy * dy) / det;
+            let oy = -(dxx * dy - dxy * dx) / det;
+
+            (x as f32 + ox.clamp(-1.0, 1.0), y as f32 + oy.clamp(-1.0, 1.0))
+        }
+    }
+}
@@ -0,0 +1,291 @@
+use std::collections::HashMap;
+use std::f32::consts::TAU;
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::SmoothingConfig;
+use crate::cv::tasks::object::BBox;
+
+use super::{Keypoints, Person};
+
+/// Overlap needed to match an untracked person to the last frame
+const MATCH_IOU: f32 = 0.3;
+
+/// Mean frame-to-frame keypoint displacement (pixels) since the last reset
+#[derive(Clone, Debug, Copy, Default, Serialize, Deserialize)]
+pub struct JitterStats {
+    pub raw: f32,
+    pub smoothed: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct LowPass {
+    value: f32,
+}
+
+impl LowPass {
+    fn filter(&mut self, x: f32, alpha: f32) -> f32 {
+        self.value = alpha * x + (1.0 - alpha) * self.value;
+        self.value
+    }
+}
+
+fn alpha(cutoff: f32, dt: f32) -> f32 {
+    let tau = 1.0 / (TAU * cutoff);
+    1.0 / (1.0 + tau / dt)
+}
+
+/// One-Euro filter for a single scalar
+#[derive(Debug, Clone, Copy)]
+struct OneEuro {
+    min_cutoff: f32,
+    beta: f32,
+    d_cutoff: f32,
+    x: LowPass,
+    dx: LowPass,
+}
+
+impl OneEuro {
+    fn new(x: f32, min_cutoff: f32, beta: f32, d_cutoff: f32) -> Self {
+        Self {
+            min_cutoff,
+            beta,
+            d_cutoff,
+            x: LowPass { value: x },
+            dx: LowPass { value: 0.0 },
+        }
+    }
+
+    fn filter(&mut self, x: f32, dt: f32) -> f32 {
+        let dx = (x - self.x.value) / dt;
+        let edx = self.dx.filter(dx, alpha(self.d_cutoff, dt));
+        let cutoff = self.min_cutoff + self.beta * edx.abs();
+        self.x.filter(x, alpha(cutoff, dt))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct KeypointFilter {
+    x: OneEuro,
+    y: OneEuro,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FilterParams {
+    min_cutoff: f32,
+    beta: f32,
+}
+
+/// Temporal filter over one person's keypoints, kept across frames
+#[derive(Debug)]
+struct KeypointSmoother {
+    params: [FilterParams; 17],
+    d_cutoff: f32,
+    confidence_threshold: f32,
+    filters: [Option<KeypointFilter>; 17],
+    last_time: Option<Instant>,
+    /// Person box from the last frame, for matching without tracking
+    bbox: BBox,
+
+    prev_raw: Keypoints,
+    prev_smoothed: Keypoints,
+}
+
+impl KeypointSmoother {
+    fn new(config: &SmoothingConfig, confidence_threshold: f32, bbox: BBox) -> Self {
+        let mut params = [FilterParams { min_cutoff: config.min_cutoff, beta: config.beta }; 17];
+        for kp in &config.keypoints {
+            if let Some(p) = params.get_mut(kp.index) {
+                p.min_cutoff = kp.min_cutoff.unwrap_or(p.min_cutoff);
+                p.beta = kp.beta.unwrap_or(p.beta);
+            }
+        }
+
+        Self {
+            params,
+            d_cutoff: config.d_cutoff,
+            confidence_threshold,
+            filters: [None; 17],
+            last_time: None,
+            bbox,
+            prev_raw: [None; 17],
+            prev_smoothed: [None; 17],
+        }
+    }
+
+    /// Filter in place, returning this frame's (raw, smoothed) displacement
+    fn apply(&mut self, keypoints: &mut Keypoints, now: Instant) -> Option<(f32, f32)> {
+        let dt = self
+            .last_time
+            .map(|t| now.duration_since(t).as_secs_f32())
+            .filter(|dt| *dt > 0.0);
+        self.last_time = Some(now);
+
+        let raw = *keypoints;
+
+        for (k, slot) in keypoints.iter_mut().enumerate() {
+            let Some((x, y, c)) = slot else {
+                self.filters[k] = None;
+                continue;
+            };
+
+            // Low-confidence positions are noise, don't let them drag the filter
+            if *c < self.confidence_threshold {
+                self.filters[k] = None;
+                continue;
+            }
+
+            let p = self.params[k];
+            match (&mut self.filters[k], dt) {
+                (Some(f), Some(dt)) => {
+                    *x = f.x.filter(*x, dt);
+                    *y = f.y.filter(*y, dt);
+                }
+                (filter, _) => {
+                    *filter = Some(KeypointFilter {
+                        x: OneEuro::new(*x, p.min_cutoff, p.beta, self.d_cutoff),
+                        y: OneEuro::new(*y, p.min_cutoff, p.beta, self.d_cutoff),
+                    });
+                }
+            }
+        }
+
+        let jitter = mean_displacement(&self.prev_raw, &raw, self.confidence_threshold)
+            .zip(mean_displacement(&self.prev_smoothed, keypoints, self.confidence_threshold));
+
+        self.prev_raw = raw;
+        self.prev_smoothed = *keypoints;
+        jitter
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum SmootherKey {
+    Track(u32),
+    /// Matched by box overlap; numbered as people appear
+    Untracked(u32),
+}
+
+/// Keeps one keypoint filter per person. People are matched across frames
+/// by track id when tracking is on, and otherwise greedily by box IoU with
+/// the last frame. Crossing people can still swap filters without tracking.
+#[derive(Debug)]
+pub struct PoseSmoother {
+    config: SmoothingConfig,
+    confidence_threshold: f32,
+    smoothers: HashMap<SmootherKey, KeypointSmoother>,
+    next_untracked: u32,
+
+    raw_total: f32,
+    smoothed_total: f32,
+    samples: u32,
+}
+
+impl PoseSmoother {
+    pub fn new(config: &SmoothingConfig, confidence_threshold: f32) -> Self {
+        Self {
+            config: config.clone(),
+            confidence_threshold,
+            smoothers: HashMap::new(),
+            next_untracked: 0,
+            raw_total: 0.0,
+            smoothed_total: 0.0,
+            samples: 0,
+        }
+    }
+
+    /// Gate current and future keypoint filters on a new threshold
+    pub fn set_threshold(&mut self, threshold: f32) {
+        self.confidence_threshold = threshold;
+        for smoother in self.smoothers.values_mut() {
+            smoother.confidence_threshold = threshold;
+        }
+    }
+
+    /// Forget all filter state, e.g. when inference restarts
+    pub fn reset(&mut self) {
+        self.smoothers.clear();
+        self.raw_total = 0.0;
+        self.smoothed_total = 0.0;
+        self.samples = 0;
+    }
+
+    pub fn jitter(&self) -> Option<JitterStats> {
+        (self.samples > 0).then(|| JitterStats {
+            raw: self.raw_total / self.samples as f32,
+            smoothed: self.smoothed_total / self.samples as f32,
+        })
+    }
+
+    pub fn apply(&mut self, people: &mut [Person], now: Instant) {
+        let seen = self.match_people(people);
+
+        for (person, key) in people.iter_mut().zip(&seen) {
+            let smoother = self
+                .smoothers
+                .entry(*key)
+                .or_insert_with(|| KeypointSmoother::new(&self.config, self.confidence_threshold, person.bbox));
+            smoother.bbox = person.bbox;
+
+            if let Some((raw, smoothed)) = smoother.apply(&mut person.keypoints, now) {
+                self.raw_total += raw;
+                self.smoothed_total += smoothed;
+                self.samples += 1;
+            }
+        }
+
+        self.smoothers.retain(|key, _| seen.contains(key));
+    }
+
+    /// Filter key for each person. Untracked people take the best free
+    /// overlap with last frame's boxes, since NMS orders them by score.
+    fn match_people(&mut self, people: &[Person]) -> Vec<SmootherKey> {
+        let mut keys: Vec<Option<SmootherKey>> = people.iter().map(|p| p.track_id.map(SmootherKey::Track)).collect();
+
+        let mut pairs = Vec::new();
+        for (i, person) in people.iter().enumerate().filter(|(i, _)| keys[*i].is_none()) {
+            for (key, smoother) in &self.smoothers {
+                let iou = person.bbox.iou(&smoother.bbox);
+                if matches!(key, SmootherKey::Untracked(_)) && iou >= MATCH_IOU {
+                    pairs.push((iou, i, *key));
+                }
+            }
+        }
+        pairs.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        let mut taken = Vec::new();
+        for (_, i, key) in pairs {
+            if keys[i].is_none() && !taken.contains(&key) {
+                keys[i] = Some(key);
+                taken.push(key);
+            }
+        }
+
+        keys.into_iter()
+            .map(|key| {
+                key.unwrap_or_else(|| {
+                    self.next_untracked = self.next_untracked.wrapping_add(1);
+                    SmootherKey::Untracked(self.next_untracked)
+                })
+            })
+            .collect()
+    }
+}
+
+fn mean_displacement(prev: &Keypoints, curr: &Keypoints, threshold: f32) -> Option<f32> {
+    let mut total = 0.0;
+    let mut count = 0;
+
+    for (a, b) in prev.iter().zip(curr) {
+        if let (Some((x1, y1, c1)), Some((x2, y2, c2))) = (a, b)
+            && *c1 >= threshold
+            && *c2 >= threshold
+        {
+            total += ((x2 - x1).powi(2) + (y2 - y1).powi(2)).sqrt();
+            count += 1;
+        }
+    }
+
+    (count > 0).then(|| total / count as f32)
+}
@@ -0,0 +1,117 @@
+use std::error::Error;
+
+use ndarray::Array4;
+use serde::{Deserialize, Serialize};
+
+use crate::config::InferenceGenericConfig;
+
+use super::{TaskResult, VisionTask, common::{numeric_values, sample_region}, object::BBox};
+
+const HISTOGRAM_BINS: usize = 16;
+
+/// Name, type and value statistics of one model output
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TensorSummary {
+    pub name: String,
+    pub dtype: String,
+    pub shape: Vec<i64>,
+    /// Unset for non-numeric outputs
+    pub stats: Option<TensorStats>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TensorStats {
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+    /// Equal-width bins over `min..=max`
+    pub histogram: Vec<u32>,
+}
+
+impl TensorStats {
+    /// Ignores NaNs; `None` when nothing is left
+    fn from_values(values: &[f32]) -> Option<Self> {
+        let finite = || values.iter().copied().filter(|v| !v.is_nan());
+
+        let count = finite().count();
+        if count == 0 {
+            return None;
+        }
+
+        let (min, max) = finite().fold((f32::MAX, f32::MIN), |(lo, hi), v| (lo.min(v), hi.max(v)));
+        let mean = (finite().map(f64::from).sum::<f64>() / count as f64) as f32;
+
+        let span = (max - min).max(f32::EPSILON);
+        let mut histogram = vec![0; HISTOGRAM_BINS];
+        for v in finite() {
+            let bin = ((v - min) / span * HISTOGRAM_BINS as f32) as usize;
+            histogram[bin.min(HISTOGRAM_BINS - 1)] += 1;
+        }
+
+        Some(Self { min, max, mean, histogram })
+    }
+}
+
+/// Runs any model and reports what comes out, for latency benchmarks of
+/// models without a dedicated task
+#[derive(Debug)]
+pub struct RawTask {
+    inf_width: usize,
+    inf_height: usize,
+}
+
+impl RawTask {
+    pub fn new(generics: &InferenceGenericConfig) -> Self {
+        Self {
+            inf_width: generics.inf_width,
+            inf_height: generics.inf_height,
+        }
+    }
+}
+
+impl VisionTask for RawTask {
+    fn preprocess(
+        &self,
+        rgba: &[u8],
+        width: u32,
+        height: u32,
+        region: &BBox,
+    ) -> Array4<f32> {
+        sample_region(rgba, width, height, region, self.inf_width, self.inf_height)
+    }
+
+    fn postprocess(
+        &self,
+        outputs: &ort::session::SessionOutputs,
+        _orig_w: u32,
+        _orig_h: u32,
+    ) -> Result<(TaskResult, usize), Box<dyn Error>> {
+        let summaries = outputs
+            .iter()
+            .map(|(name, value)| {
+                let dtype = value.dtype();
+                let shape = dtype.tensor_shape().map(|s| s.to_vec()).unwrap_or_default();
+                let stats = numeric_values(&value).and_then(|values| TensorStats::from_values(&values));
+
+                TensorSummary {
+                    name: name.to_string(),
+                    dtype: dtype.to_string(),
+                    shape,
+                    stats,
+                }
+            })
+            .collect();
+
+        Ok((TaskResult::Raw(summaries), 0))
+    }
+
+    /// Nothing to draw; the summaries are shown in the UI panel instead
+    fn render(
+        &self,
+        _result: &TaskResult,
+        width: u32,
+        height: u32,
+    ) -> Vec<u8> {
+        vec![0; (width * height * 4) as usize]
+    }
+}
@@ -0,0 +1,85 @@
+use raqote::SolidSource;
+
+use crate::config::OverlayConfig;
+use crate::cv::tracking::track_color;
+
+/// Overlay sizes and colors shared by all renderers
+#[derive(Debug, Clone)]
+pub struct OverlayStyle {
+    config: OverlayConfig,
+}
+
+impl OverlayStyle {
+    pub fn new(config: &OverlayConfig) -> Self {
+        Self { config: config.clone() }
+    }
+
+    fn scale(&self, height: u32) -> f32 {
+        height as f32 / self.config.reference_height.max(1) as f32
+    }
+
+    pub fn line_width(&self, height: u32) -> f32 {
+        self.config.line_width * self.scale(height)
+    }
+
+    pub fn point_radius(&self, height: u32) -> f32 {
+        self.config.point_radius * self.scale(height)
+    }
+
+    pub fn font_size(&self, height: u32) -> f32 {
+        self.config.font_size * self.scale(height)
+    }
+
+    pub fn show_confidence(&self) -> bool {
+        self.config.show_confidence
+    }
+
+    /// Name and/or confidence drawn next to a keypoint, if enabled
+    pub fn keypoint_label(&self, name: &str, confidence: f32) -> Option<String> {
+        match (self.config.show_keypoint_names, self.config.show_keypoint_confidence) {
+            (true, true) => Some(format!("{name} {:.0}%", confidence * 100.0)),
+            (true, false) => Some(name.to_string()),
+            (false, true) => Some(format!("{:.0}%", confidence * 100.0)),
+            (false, false) => None,
+        }
+    }
+
+    /// Track color when tracked, else the class palette
+    pub fn box_color(&self, class_id: usize, track_id: Option<u32>) -> (u8, u8, u8) {
+        match (track_id, self.config.class_colors.as_slice()) {
+            (Some(id), _) => track_color(id),
+            (None, []) => track_color(class_id as u32),
+            (None, palette) => rgb(palette[class_id % palette.len()]),
+        }
+    }
+
+    pub fn limb_color(&self, limb: usize) -> Option<(u8, u8, u8)> {
+        self.config.limb_colors.get(limb).copied().map(rgb)
+    }
+
+    pub fn keypoint_color(&self, index: usize, default: (u8, u8, u8)) -> (u8, u8, u8) {
+        self.config.keypoint_colors.get(index).copied().map_or(default, rgb)
+    }
+
+    pub fn text_color(&self) -> SolidSource {
+        solid(rgb(self.config.text_color))
+    }
+
+    pub fn label_background(&self) -> SolidSource {
+        let [r, g, b, a] = self.config.label_background;
+        translucent((r, g, b), a)
+    }
+}
+
+fn rgb([r, g, b]: [u8; 3]) -> (u8, u8, u8) {
+    (r, g, b)
+}
+
+pub fn solid((r, g, b): (u8, u8, u8)) -> SolidSource {
+    SolidSource { r, g, b, a: 255 }
+}
+
+/// raqote sources are premultiplied
+pub fn translucent((r, g, b): (u8, u8, u8), a: u8) -> SolidSource {
+    SolidSource::from_unpremultiplied_argb(a, r, g, b)
+}
@@ -0,0 +1,71 @@
+use std::error::Error;
+
+use crate::config::DetectorConfig;
+
+use super::{TopDownMetrics, cv_inference::Stage, tasks::{BBox, ObjectTask, TaskResult}};
+
+/// First stage of a top-down pose pipeline: finds people, then hands each
+/// padded crop to the pose stage and maps its keypoints back to the frame.
+#[derive(Debug)]
+pub struct Detector {
+    stage: Stage,
+    person_class: usize,
+    box_padding: f32,
+    /// Pose model input width / height
+    aspect: f32,
+}
+
+impl Detector {
+    pub fn from_config(config: &DetectorConfig, pose_aspect: f32) -> Result<Self, Box<dyn Error>> {
+        let task = Box::new(ObjectTask::new(&config.generics, &config.object.clone().unwrap_or_default()));
+
+        Ok(Self {
+            stage: Stage::new(&config.model_path, &config.generics, task)?,
+            person_class: config.person_class,
+            box_padding: config.box_padding,
+            aspect: pose_aspect,
+        })
+    }
+
+    pub fn run(
+        &mut self,
+        pose: &mut Stage,
+        rgba: &[u8],
+        width: u32,
+        height: u32,
+    ) -> Result<(TaskResult, usize, TopDownMetrics), Box<dyn Error>> {
+        let (result, mut discarded, detector) = self.stage.run(rgba, width, height, &BBox::frame(width, height))?;
+        let TaskResult::Detections(detections) = result else {
+            return Err("Detector did not return detections".into());
+        };
+
+        let mut metrics = TopDownMetrics { detector, people: Vec::new() };
+        let mut people = Vec::new();
+
+        for det in detections.iter().filter(|d| d.class_id == self.person_class) {
+            if det.bbox.area() < 1.0 {
+                continue;
+            }
+
+            let region = det.bbox.padded_to_aspect(self.box_padding, self.aspect);
+            let (result, crop_discarded, person_metrics) = pose.run(rgba, width, height, &region)?;
+            metrics.people.push(person_metrics);
+            discarded += crop_discarded;
+
+            // Each crop should hold one person, the highest scoring one comes first
+            if let TaskResult::Pose(found) = result
+                && let Some(mut person) = found.into_iter().next()
+            {
+                for (x, y, _) in person.keypoints.iter_mut().flatten() {
+                    *x += region.x1;
+                    *y += region.y1;
+                }
+                person.bbox = det.bbox;
+                person.score = det.score;
+                people.push(person);
+            }
+        }
+
+        Ok((TaskResult::Pose(people), discarded, metrics))
+    }
+}
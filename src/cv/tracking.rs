@@ -0,0 +1,221 @@
+use crate::config::TrackingConfig;
+
+use super::tasks::{BBox, Detection};
+
+/// Constant-velocity Kalman filter on a single coordinate
+#[derive(Debug, Clone, Copy)]
+struct Kalman1D {
+    x: f32,
+    v: f32,
+    p: [[f32; 2]; 2],
+}
+
+impl Kalman1D {
+    // Velocity starts out unknown, so give it a much larger variance than position
+    fn new(x: f32) -> Self {
+        Self { x, v: 0.0, p: [[10.0, 0.0], [0.0, 1000.0]] }
+    }
+
+    fn predict(&mut self, q: f32) {
+        self.x += self.v;
+
+        let [[p00, p01], [p10, p11]] = self.p;
+        self.p = [
+            [p00 + p01 + p10 + p11 + 0.25 * q, p01 + p11 + 0.5 * q],
+            [p10 + p11 + 0.5 * q, p11 + q],
+        ];
+    }
+
+    fn update(&mut self, z: f32, r: f32) {
+        let [[p00, p01], [p10, p11]] = self.p;
+        let s = p00 + r;
+        let (k0, k1) = (p00 / s, p10 / s);
+
+        let y = z - self.x;
+        self.x += k0 * y;
+        self.v += k1 * y;
+
+        self.p = [
+            [(1.0 - k0) * p00, (1.0 - k0) * p01],
+            [p10 - k1 * p00, p11 - k1 * p01],
+        ];
+    }
+}
+
+const PROCESS_NOISE: f32 = 1.0;
+const MEASUREMENT_NOISE: f32 = 10.0;
+
+#[derive(Debug, Clone)]
+struct Track {
+    id: u32,
+    class_id: usize,
+    // cx, cy, w, h
+    state: [Kalman1D; 4],
+    hits: u32,
+    misses: u32,
+}
+
+impl Track {
+    fn new(id: u32, det: &Detection) -> Self {
+        let (cx, cy) = det.bbox.center();
+        Self {
+            id,
+            class_id: det.class_id,
+            state: [
+                Kalman1D::new(cx),
+                Kalman1D::new(cy),
+                Kalman1D::new(det.bbox.width()),
+                Kalman1D::new(det.bbox.height()),
+            ],
+            hits: 1,
+            misses: 0,
+        }
+    }
+
+    fn bbox(&self) -> BBox {
+        let [cx, cy, w, h] = self.state.map(|k| k.x);
+        BBox::from_cxcywh(cx, cy, w.max(0.0), h.max(0.0))
+    }
+
+    fn predict(&mut self) {
+        for k in &mut self.state {
+            k.predict(PROCESS_NOISE);
+        }
+    }
+
+    fn update(&mut self, bbox: &BBox) {
+        let (cx, cy) = bbox.center();
+        for (k, z) in self.state.iter_mut().zip([cx, cy, bbox.width(), bbox.height()]) {
+            k.update(z, MEASUREMENT_NOISE);
+        }
+        self.hits += 1;
+        self.misses = 0;
+    }
+}
+
+/// SORT-style tracker with ByteTrack's second association pass for
+/// low-score detections. Matching is greedy on IoU, per class.
+#[derive(Debug)]
+pub struct Tracker {
+    config: TrackingConfig,
+    tracks: Vec<Track>,
+    next_id: u32,
+}
+
+impl Tracker {
+    pub fn new(config: &TrackingConfig) -> Self {
+        Self {
+            config: config.clone(),
+            tracks: Vec::new(),
+            next_id: 1,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.tracks.clear();
+        self.next_id = 1;
+    }
+
+    /// Advance one frame and return the confirmed track id for each detection
+    pub fn update(&mut self, detections: &[Detection]) -> Vec<Option<u32>> {
+        for track in &mut self.tracks {
+            track.predict();
+        }
+
+        let (high, low): (Vec<usize>, Vec<usize>) = (0..detections.len())
+            .partition(|&i| detections[i].score >= self.config.high_score);
+
+        let mut track_for_det: Vec<Option<usize>> = vec![None; detections.len()];
+        let mut free_tracks: Vec<usize> = (0..self.tracks.len()).collect();
+
+        for candidates in [high.as_slice(), low.as_slice()] {
+            for (t, d) in self.associate(detections, candidates, &free_tracks) {
+                track_for_det[d] = Some(t);
+                free_tracks.retain(|&f| f != t);
+            }
+        }
+
+        for (d, t) in track_for_det.iter().enumerate() {
+            if let Some(t) = *t {
+                self.tracks[t].update(&detections[d].bbox);
+            }
+        }
+        for &t in &free_tracks {
+            self.tracks[t].misses += 1;
+        }
+
+        // Only confident detections are allowed to start a track
+        for &d in &high {
+            if track_for_det[d].is_none() {
+                track_for_det[d] = Some(self.tracks.len());
+                self.tracks.push(Track::new(self.next_id, &detections[d]));
+                self.next_id += 1;
+            }
+        }
+
+        let ids = track_for_det
+            .iter()
+            .map(|t| {
+                t.map(|t| &self.tracks[t])
+                    .filter(|track| track.hits >= self.config.min_hits)
+                    .map(|track| track.id)
+            })
+            .collect();
+
+        let max_age = self.config.max_age;
+        self.tracks.retain(|t| t.misses <= max_age);
+
+        ids
+    }
+
+    fn associate(
+        &self,
+        detections: &[Detection],
+        candidates: &[usize],
+        free_tracks: &[usize],
+    ) -> Vec<(usize, usize)> {
+        let mut pairs = Vec::new();
+        for &t in free_tracks {
+            let track = &self.tracks[t];
+            let predicted = track.bbox();
+            for &d in candidates {
+                if detections[d].class_id != track.class_id {
+                    continue;
+                }
+                let iou = predicted.iou(&detections[d].bbox);
+                if iou >= self.config.iou_threshold {
+                    pairs.push((iou, t, d));
+                }
+            }
+        }
+        pairs.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        let mut used_tracks = Vec::new();
+        let mut used_dets = Vec::new();
+        let mut matches = Vec::new();
+        for (_, t, d) in pairs {
+            if used_tracks.contains(&t) || used_dets.contains(&d) {
+                continue;
+            }
+            used_tracks.push(t);
+            used_dets.push(d);
+            matches.push((t, d));
+        }
+        matches
+    }
+}
+
+/// Stable, well-spread color for a track id (golden-ratio hue steps)
+pub fn track_color(id: u32) -> (u8, u8, u8) {
+    let h = (id as f32 * 0.618_034).fract() * 6.0;
+    let x = 1.0 - (h % 2.0 - 1.0).abs();
+    let (r, g, b) = match h as u32 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    ((r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8)
+}
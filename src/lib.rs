@@ -7,26 +7,31 @@ pub mod utils;
 use std::sync::{Arc, Mutex};
 
 use camera::{CameraManager, Frame};
-use cv::{CVManager, Inference};
+use cv::{CVManager, Inference, Recorder};
 use config::Config;
 
 pub use app::run;
 
+/// Read at startup; live-tuned settings are saved back here
+pub const CONFIG_PATH: &str = "config.toml";
+
 pub type SharedFrame = Arc<Mutex<Option<Frame>>>;
 
 #[derive(Clone, Debug)]
 pub struct Pipelines {
     pub camera_manager: Arc<CameraManager>,
-    pub cv_manager: Arc<CVManager>
+    pub cv_manager: Arc<CVManager>,
+    pub recorder: Arc<Recorder>,
 }
 
 pub fn new_pipelines() -> Pipelines {
-    let config = Config::load("config.toml").expect("Unable to load config");
+    let config = Config::load(CONFIG_PATH).expect("Unable to load config");
 
     let shared_frame: SharedFrame = Arc::new(Mutex::new(None));
 
     let camera_manager = Arc::new(CameraManager::new(config.camera, shared_frame.clone()));
-    let cv_manager = Arc::new(CVManager::new(config.model, shared_frame.clone()));
+    let recorder = Arc::new(Recorder::new(config.recording));
+    let cv_manager = Arc::new(CVManager::new(config.model, config.export, shared_frame.clone(), recorder.clone()));
 
-    Pipelines { camera_manager, cv_manager }
+    Pipelines { camera_manager, cv_manager, recorder }
 }
\ No newline at end of file
@@ -1,6 +1,9 @@
-use std::{error::Error, sync::{Arc, atomic::{AtomicBool, Ordering}}};
+use std::{error::Error, sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}}, thread::{self, JoinHandle}, time::{Duration, Instant}};
 use tokio::sync::broadcast;
 
+/// How long `stop` waits for the worker thread to exit
+pub const STOP_TIMEOUT: Duration = Duration::from_secs(2);
+
 pub trait ManagedService {
     type Output: Clone;
 
@@ -8,27 +11,171 @@ pub trait ManagedService {
 
     fn start(&self) -> Result<(), Box<dyn Error>>;
 
-    fn stop(&self) {
-        self.core().running.store(false, Ordering::SeqCst);
+    /// Signal the worker to exit and wait for it, up to a timeout
+    fn stop(&self) -> Result<(), Box<dyn Error>> {
+        self.core().stop(STOP_TIMEOUT)
     }
 
     fn subscribe(&self) -> broadcast::Receiver<Self::Output> {
         self.core().tx.subscribe()
     }
+
+    fn state(&self) -> ServiceState {
+        self.core().state()
+    }
+
+    /// Every state change from now on
+    fn status(&self) -> broadcast::Receiver<ServiceState> {
+        self.core().status_tx.subscribe()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServiceState {
+    Stopped,
+    Starting,
+    Running,
+    Stopping,
+    Failed(String),
 }
 
 #[derive(Debug, Clone)]
 pub struct ServiceCore<T: Clone> {
     pub running: Arc<AtomicBool>,
     pub tx: broadcast::Sender<T>,
+    state: Arc<Mutex<ServiceState>>,
+    status_tx: broadcast::Sender<ServiceState>,
+    handle: Arc<Mutex<Option<JoinHandle<()>>>>,
 }
 
 impl<T: Clone> ServiceCore<T> {
     pub fn new(buffer: usize) -> Self {
         let (tx, _) = broadcast::channel(buffer);
+        let (status_tx, _) = broadcast::channel(16);
         Self {
             running: Arc::new(AtomicBool::new(false)),
             tx,
+            state: Arc::new(Mutex::new(ServiceState::Stopped)),
+            status_tx,
+            handle: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn state(&self) -> ServiceState {
+        self.state.lock().unwrap().clone()
+    }
+
+    fn set_state(&self, state: ServiceState) {
+        self.update_state(|_| Some(state));
+    }
+
+    /// Check and change the state under one lock, so the worker can't exit
+    /// in between. `change` returns the new state, or `None` to keep it.
+    fn update_state(&self, change: impl FnOnce(&ServiceState) -> Option<ServiceState>) {
+        let mut current = self.state.lock().unwrap();
+        if let Some(state) = change(&current) {
+            *current = state.clone();
+            // Sent under the lock so subscribers see changes in order
+            let _ = self.status_tx.send(state);
+        }
+    }
+
+    /// Move to `Starting`. Fails while a previous worker is still alive.
+    pub fn begin_start(&self) -> Result<(), Box<dyn Error>> {
+        let mut handle = self.handle.lock().unwrap();
+        if let Some(previous) = handle.take() {
+            if !previous.is_finished() {
+                *handle = Some(previous);
+                return Err("Previous worker has not stopped yet".into());
+            }
+            let _ = previous.join();
+        }
+
+        self.running.store(true, Ordering::SeqCst);
+        self.set_state(ServiceState::Starting);
+        Ok(())
+    }
+
+    /// Record a failed start
+    pub fn fail(&self, error: &dyn Error) {
+        self.running.store(false, Ordering::SeqCst);
+        self.set_state(ServiceState::Failed(error.to_string()));
+    }
+
+    /// Run `body` on the worker thread. When it returns the service is
+    /// `Stopped`, or `Failed` with its error.
+    pub fn spawn(
+        &self,
+        name: &str,
+        body: impl FnOnce() -> Result<(), String> + Send + 'static,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        T: Send + 'static,
+    {
+        let core = self.clone();
+        self.set_state(ServiceState::Running);
+
+        let spawned = thread::Builder::new()
+            .name(name.to_string())
+            .spawn(move || {
+                let result = body();
+                core.running.store(false, Ordering::SeqCst);
+                core.set_state(match result {
+                    Ok(()) => ServiceState::Stopped,
+                    Err(e) => ServiceState::Failed(e),
+                });
+            });
+
+        match spawned {
+            Ok(handle) => {
+                *self.handle.lock().unwrap() = Some(handle);
+                Ok(())
+            }
+            Err(e) => {
+                self.fail(&e);
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Signal the worker and wait up to `timeout` for it to exit. On timeout
+    /// the service stays `Stopping` and can't be restarted until it exits.
+    pub fn stop(&self, timeout: Duration) -> Result<(), Box<dyn Error>> {
+        self.running.store(false, Ordering::SeqCst);
+
+        let Some(handle) = self.handle.lock().unwrap().take() else {
+            return Ok(());
+        };
+        self.update_state(|state| {
+            matches!(state, ServiceState::Starting | ServiceState::Running).then_some(ServiceState::Stopping)
+        });
+
+        let deadline = Instant::now() + timeout;
+        while !handle.is_finished() {
+            if Instant::now() >= deadline {
+                *self.handle.lock().unwrap() = Some(handle);
+                return Err(format!("Worker did not stop within {timeout:?}").into());
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        if handle.join().is_err() {
+            self.set_state(ServiceState::Failed("Worker thread panicked".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Sleeps in short steps so a stop isn't held up. Returns false when
+/// `running` was cleared first.
+pub fn sleep_while(running: &AtomicBool, duration: Duration) -> bool {
+    let deadline = Instant::now() + duration;
+    while running.load(Ordering::SeqCst) {
+        let now = Instant::now();
+        if now >= deadline {
+            return true;
         }
+        thread::sleep((deadline - now).min(Duration::from_millis(50)));
     }
+    false
 }